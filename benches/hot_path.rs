@@ -0,0 +1,308 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use memmap2::Mmap;
+use onebrc::{
+    count_newlines, multi_thread, parse_next_row, process_chunk, table::CityTable,
+    timer::PhaseTimer, Extras, MeasurementIter, ParseOptions, Stats,
+};
+use rustc_hash::{FxHashMap, FxHasher};
+use std::{env, fs, fs::File, hash::BuildHasherDefault};
+
+const CITIES: [&str; 10] = [
+    "Hamburg",
+    "Bulawayo",
+    "Palembang",
+    "St. John's",
+    "Cracow",
+    "Bridgetown",
+    "Istanbul",
+    "Roseau",
+    "Conakry",
+    "Abidjan",
+];
+
+/// Endless well-formed rows cycling through [`CITIES`], with temperatures
+/// from a fixed linear congruential sequence.
+fn synthetic_rows() -> impl Iterator<Item = String> {
+    let mut state: u32 = 1;
+    CITIES.iter().cycle().map(move |city| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let measure = (state >> 16) as i32 % 1999 - 999;
+        let sign = if measure < 0 { "-" } else { "" };
+        format!(
+            "{city};{sign}{}.{}\n",
+            measure.abs() / 10,
+            measure.abs() % 10
+        )
+    })
+}
+
+/// At least `len` bytes of [`synthetic_rows`].
+fn synthetic_buffer(len: usize) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(len + 32);
+    for row in synthetic_rows() {
+        if buffer.len() >= len {
+            break;
+        }
+        buffer.extend_from_slice(row.as_bytes());
+    }
+    buffer
+}
+
+/// A `len` byte row whose newline is its last byte.
+fn row_of_len(len: usize) -> Vec<u8> {
+    let city = "a".repeat(len - ";12.3\n".len());
+    format!("{city};12.3\n").into_bytes()
+}
+
+fn bench_parse_next_row(c: &mut Criterion) {
+    let buffer: String = synthetic_rows().take(1_000_000).collect();
+    let buffer = buffer.into_bytes();
+    let mut group = c.benchmark_group("parse_next_row");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.bench_function("1M rows", |b| {
+        b.iter(|| {
+            let buffer = black_box(buffer.as_slice());
+            let mut i = 0;
+            while i < buffer.len() {
+                let row = parse_next_row(&buffer[i..], b';').unwrap();
+                i += row.bytes_consumed;
+                black_box(row);
+            }
+        })
+    });
+    group.finish();
+}
+
+/// There is no standalone newline search in this crate; `parse_next_row`
+/// scans for it first, so rows of a given length measure that scan.
+fn bench_newline_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("newline_search");
+    for len in [64, 128, 256] {
+        let row = row_of_len(len);
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &row, |b, row| {
+            b.iter(|| parse_next_row(black_box(row), b';').unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Newline at a given position within a 64 byte buffer, or absent when the
+/// row is the last one of a chunk. A newline at position 0 would end an
+/// empty row, which the parser rejects, so the shortest case starts at 31.
+fn bench_newline_in_chunk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("newline_in_chunk");
+    group.throughput(Throughput::Bytes(64));
+    for pos in [31, 63] {
+        let mut buffer = row_of_len(pos + 1);
+        buffer.resize(64, b'b');
+        group.bench_with_input(BenchmarkId::from_parameter(pos), &buffer, |b, buffer| {
+            b.iter(|| parse_next_row(black_box(buffer), b';').unwrap())
+        });
+    }
+    let mut buffer = row_of_len(64);
+    buffer.pop();
+    group.bench_with_input(
+        BenchmarkId::from_parameter("absent"),
+        &buffer,
+        |b, buffer| b.iter(|| parse_next_row(black_box(buffer), b';').unwrap()),
+    );
+    group.finish();
+}
+
+fn bench_count_newlines(c: &mut Criterion) {
+    let buffer = synthetic_buffer(10 * 1024 * 1024);
+    let mut group = c.benchmark_group("count_newlines");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.bench_function("10MB", |b| b.iter(|| count_newlines(black_box(&buffer))));
+    group.finish();
+}
+
+/// Rows of `cities` cities that are not stations of the official dataset,
+/// so [`CityTable`] has to hash them like any map would.
+fn other_cities_buffer(len: usize, cities: usize) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(len + 32);
+    for (i, row) in synthetic_rows().enumerate() {
+        if buffer.len() >= len {
+            break;
+        }
+        let temperature = &row[row.find(';').unwrap()..];
+        buffer.extend_from_slice(format!("Town {}{temperature}", i % cities).as_bytes());
+    }
+    buffer
+}
+
+/// The aggregation of one chunk into [`CityTable`] against the same loop
+/// into an `FxHashMap`, for stations and for cities it has to hash.
+fn bench_city_table(c: &mut Criterion) {
+    let mut group = c.benchmark_group("city_table");
+    for (name, buffer) in [
+        ("stations", synthetic_buffer(1024 * 1024)),
+        ("other cities", other_cities_buffer(1024 * 1024, 1000)),
+    ] {
+        group.throughput(Throughput::Bytes(buffer.len() as u64));
+        group.bench_with_input(BenchmarkId::new("CityTable", name), &buffer, |b, buffer| {
+            b.iter(|| {
+                let mut table = CityTable::default();
+                process_chunk(
+                    black_box(buffer),
+                    &mut table,
+                    Extras::default(),
+                    ParseOptions::default(),
+                )
+                .unwrap();
+                table
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("FxHashMap", name), &buffer, |b, buffer| {
+            b.iter(|| {
+                let mut map: FxHashMap<&[u8], Stats> = FxHashMap::default();
+                for row in MeasurementIter::new(black_box(buffer)) {
+                    let (city, measure) = row.unwrap();
+                    match map.get_mut(city) {
+                        Some(stats) => stats.update(measure),
+                        None => {
+                            map.insert(city, Stats::new(measure, Extras::default()));
+                        }
+                    }
+                }
+                map
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_end_to_end(c: &mut Criterion) {
+    let buffer = synthetic_buffer(10 * 1024 * 1024);
+    let mut group = c.benchmark_group("end_to_end");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.sample_size(10);
+    group.bench_function("single_thread 10MB", |b| {
+        b.iter(|| {
+            let mut timer = PhaseTimer::new();
+            multi_thread(
+                black_box(buffer.as_slice()),
+                1,
+                buffer.len(),
+                Extras::default(),
+                ParseOptions::default(),
+                BuildHasherDefault::<FxHasher>::default(),
+                &mut timer,
+            )
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// Many workers on 10k cities, so that merging their tables is a sizeable
+/// part of the run.
+fn bench_merge(c: &mut Criterion) {
+    let buffer = other_cities_buffer(10 * 1024 * 1024, 10_000);
+    let mut group = c.benchmark_group("merge");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.sample_size(10);
+    for workers in [32, 64] {
+        group.bench_with_input(
+            BenchmarkId::new("workers", workers),
+            &workers,
+            |b, &workers| {
+                b.iter(|| {
+                    multi_thread(
+                        black_box(buffer.as_slice()),
+                        workers,
+                        buffer.len() / workers,
+                        Extras::default(),
+                        ParseOptions::default(),
+                        BuildHasherDefault::<FxHasher>::default(),
+                        &mut PhaseTimer::new(),
+                    )
+                    .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// The per-worker tables against the one map all workers update with
+/// atomics under `--lock-free`, on rows of only ten cities so the workers
+/// contend for the same cells.
+fn bench_lock_free(c: &mut Criterion) {
+    let buffer = synthetic_buffer(10 * 1024 * 1024);
+    let mut group = c.benchmark_group("lock_free");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.sample_size(10);
+    group.bench_function("tables", |b| {
+        b.iter(|| {
+            multi_thread(
+                black_box(buffer.as_slice()),
+                4,
+                40_000,
+                Extras::default(),
+                ParseOptions::default(),
+                BuildHasherDefault::<FxHasher>::default(),
+                &mut PhaseTimer::new(),
+            )
+            .unwrap()
+        })
+    });
+    #[cfg(feature = "lock-free")]
+    group.bench_function("shared map", |b| {
+        b.iter(|| {
+            onebrc::lock_free::multi_thread(
+                black_box(buffer.as_slice()),
+                4,
+                40_000,
+                ParseOptions::default(),
+                &mut PhaseTimer::new(),
+            )
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// Counting the rows of a 64 MB file after mapping it, after reading it
+/// into a `Vec` like `--no-mmap`, and after reading it with io_uring like
+/// `--io-uring`. The file is in the page cache after the first run, so this
+/// measures the copies and page faults rather than the disk.
+fn bench_read_file(c: &mut Criterion) {
+    let buffer = synthetic_buffer(64 * 1024 * 1024);
+    let path = env::temp_dir().join(format!("onebrc-bench-{}.txt", std::process::id()));
+    fs::write(&path, &buffer).unwrap();
+    let mut group = c.benchmark_group("read_file");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.sample_size(10);
+    group.bench_function("mmap", |b| {
+        b.iter(|| {
+            let file = File::open(&path).unwrap();
+            let mmap = unsafe { Mmap::map(&file) }.unwrap();
+            count_newlines(&mmap)
+        })
+    });
+    group.bench_function("read", |b| {
+        b.iter(|| count_newlines(&fs::read(&path).unwrap()))
+    });
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    group.bench_function("io_uring", |b| {
+        b.iter(|| count_newlines(&onebrc::uring::read_file(path.to_str().unwrap()).unwrap()))
+    });
+    group.finish();
+    fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(
+    benches,
+    bench_parse_next_row,
+    bench_newline_search,
+    bench_newline_in_chunk,
+    bench_count_newlines,
+    bench_city_table,
+    bench_end_to_end,
+    bench_merge,
+    bench_lock_free,
+    bench_read_file
+);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use onebrc::chunks;
+
+fuzz_target!(|input: (u16, bool, &[u8])| {
+    let (chunk_size, huge, data) = input;
+    // Zero is rejected by an assertion, see `it_rejects_zero_chunks`.
+    let chunk_size = if huge {
+        usize::MAX
+    } else if chunk_size == 0 {
+        return;
+    } else {
+        chunk_size as usize
+    };
+
+    // Chunks are split at a newline, which is dropped, so joining them back
+    // with newlines gives the input. A trailing newline is only kept when it
+    // falls inside the last chunk.
+    let joined = chunks(data, chunk_size).join(&b'\n');
+    assert!(joined == data || Some(joined.as_slice()) == data.strip_suffix(b"\n"));
+});
@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use onebrc::{
+    args::Unit,
+    output::{write_stats, DEFAULT_COLUMNS},
+    parse_next_row, process_chunk,
+    table::CityTable,
+    Extras, ParseOptions,
+};
+
+// Fuzz builds keep debug assertions, so overflows in the temperature
+// arithmetic panic like out-of-bounds slicing does.
+fuzz_target!(|data: &[u8]| {
+    let Ok(row) = parse_next_row(data, b';') else {
+        return;
+    };
+    let last = row.bytes_consumed;
+    assert!(row.city.len() < last && last <= data.len());
+
+    // Cities are arbitrary bytes, which must not break the output.
+    let mut table = CityTable::default();
+    process_chunk(
+        &data[..last],
+        &mut table,
+        Extras::default(),
+        ParseOptions::default(),
+    )
+    .unwrap();
+    let mut out = vec![];
+    for (city, stats) in table.into_entries() {
+        write_stats(&mut out, city, &stats, DEFAULT_COLUMNS, Unit::Celsius).unwrap();
+    }
+});
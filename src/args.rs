@@ -0,0 +1,1359 @@
+use crate::{output::Column, Extras, ParseOptions};
+use std::{
+    ffi::OsStr,
+    fmt::{self, Display, Formatter},
+};
+
+pub const USAGE: &str = "Usage: onebrc [OPTIONS] [FILE]...
+
+Arguments:
+  [FILE]...             Measurements files, or - for stdin, aggregated as one
+                        [default: measurements.txt]
+
+Options:
+  --threads N           Number of worker threads [default: available cores]
+  --chunk-size BYTES    Bytes handed to a worker at a time, at least 128
+                        [default: 40000]
+  --huge-pages          Ask for transparent huge pages on the mapping, to
+                        reduce TLB misses. Linux only; needs
+                        transparent_hugepage set to madvise or always and
+                        does not help on every system
+  --io-uring            Read the file with io_uring instead of mapping it.
+                        Linux only, needs the io-uring feature
+  --no-mmap             Read the file into memory instead of mapping it,
+                        e.g. on network file systems
+  --gzip                Decompress gzip files while aggregating them on a
+                        single thread. Needs the gzip feature and cannot be
+                        combined with --line-count, --unique-cities or
+                        --check
+  --skip-header         Ignore the first line of every file, e.g. a
+                        city;temperature header
+  --allow-comments      Ignore lines starting with #
+  --delimiter CHAR      Byte between the city and the temperature, e.g. |
+                        or \\t for a tab [default: ;]
+  --validate-range      Warn about and skip rows with a temperature beyond
+                        ±99.9 instead of failing on them
+  --output FILE         Write the results to FILE instead of stdout,
+                        truncating it if it exists
+  --output-buffer-size BYTES
+                        Results buffered before each write, 0 to hand every
+                        entry to stdout or FILE at once [default: 65536]
+  --format FORMAT       Output format: default, json, ndjson, csv, tsv,
+                        prometheus, table, or raw for onebrc merge --raw
+                        [default: default]
+  --one-per-line        Print every city on its own line, without braces,
+                        for sort, grep or awk. Only for the default format
+  --no-color            Do not color the default format, which is colored
+                        when printed to a terminal unless NO_COLOR is set
+  --fahrenheit          Print temperatures in degrees Fahrenheit, marked °F
+                        in the default format
+  --select FIELDS       Comma separated statistics to print, in that order,
+                        out of min, avg, max, count and sum
+                        [default: min,avg,max]
+  --sort-by KEY         Order cities by name, min, max, avg or count.
+                        Statistics sort descending, ties by name
+                        [default: name]
+  --sort-mode MODE      How to order the merged cities by name before any
+                        --sort-by: btree or vec [default: btree]
+  --top-n N             Only print the N cities with the highest max, or the
+                        first N in --sort-by order
+  --filter PATTERN      Only print cities whose name contains PATTERN,
+                        ignoring case
+  --exclude PATTERN     Drop cities whose name contains PATTERN, ignoring
+                        case. Applied after --filter
+  --min-count N         Only print cities with at least N measurements. Does
+                        not change the aggregation or --summary
+  --hasher HASHER       Hash function for city names: fx, or ahash when
+                        built with the ahash feature [default: fx]
+  --lock-free           Aggregate into one map shared by all threads with
+                        atomic updates, hashing with fx. Needs the
+                        lock-free feature and cannot be combined with
+                        --hasher, --gzip, --median, --percentiles or
+                        --ascii-histogram
+  --line-count          Print the number of rows and exit without
+                        aggregating
+  --unique-cities       Print the number of distinct cities and exit
+                        without computing statistics
+  --check               Only parse the input: print every malformed line to
+                        stderr and the number of lines and errors, and
+                        fail if there were any
+  --no-timing           Do not print the elapsed time to stderr
+  --dry-run             Parse and aggregate without writing any results
+  --verbose             Print a per-phase timing breakdown and the
+                        throughput to stderr
+  --summary             Print the number of rows and cities and the overall
+                        min, max and mean to stderr
+  --count               Append the number of measurements
+  --median              Append the median, estimated from a sample of up to
+                        1024 measurements per city
+  --percentiles LIST    Append the comma-separated percentiles, such as
+                        p50,p90,p99, estimated from the same sample as
+                        --median
+  --stddev              Append the population standard deviation
+  --ascii-histogram     Chart how the measurements of every city spread
+                        over 10°C bins after the results
+
+Commands:
+  generate              Write a synthetic measurements file
+  validate              Check the results for a file against a reference
+  merge                 Combine the --format raw results of several runs
+
+Workers repeatedly claim the next chunk until the input is exhausted, so
+--threads and --chunk-size are independent: smaller chunks balance the load
+better, larger ones reduce coordination. Chunks that fit in the L2/L3 cache
+tend to perform best, so the optimal size depends on the hardware.
+";
+
+pub const GENERATE_USAGE: &str =
+    "Usage: onebrc generate --rows N --cities M --output FILE [--seed S]
+
+Options:
+  --rows N              Number of measurements to write
+  --cities M            Number of distinct random city names, 5 to 15 ASCII
+                        letters each
+  --output FILE         File to write, truncating it if it exists
+  --seed S              Seed for the random generator, for reproducible
+                        files [default: random]
+
+Temperatures are uniform in [-99.9, 99.9] with one decimal.
+";
+
+pub const VALIDATE_USAGE: &str = "Usage: onebrc validate --input FILE --expected FILE
+
+Options:
+  --input FILE          Measurements file to aggregate
+  --expected FILE       Reference results in the default format
+
+Lists every city whose min or max differ, or whose average is off by more
+than 0.005, and exits with 1 if there is any.
+";
+
+pub const MERGE_USAGE: &str = "Usage: onebrc merge --raw FILE...
+
+Options:
+  --raw                 Read the files as the output of --format raw, the
+                        only format that can be merged
+
+Prints the results of the runs as if their inputs had been aggregated
+together, in the default format. The raw output keeps no sample of the
+measurements, so there is no median or standard deviation to merge.
+";
+
+const DEFAULT_PATH: &str = "measurements.txt";
+const DEFAULT_CHUNK_SIZE: usize = 40_000;
+/// Large enough to write most results with a single syscall.
+const DEFAULT_OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+/// Large enough for at least one complete row of the longest legal city name.
+const MIN_CHUNK_SIZE: usize = 128;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// `{city=min/avg/max, ...}`
+    #[default]
+    Default,
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    Csv,
+    Tsv,
+    /// Prometheus text format, one gauge per statistic.
+    Prometheus,
+    /// Aligned columns under a header, for reading.
+    Table,
+    /// `city\tsum\tcount\tmin\tmax` in tenths of a degree, for `merge`.
+    Raw,
+}
+
+/// The scale temperatures are printed in. Measurements are always read and
+/// aggregated in Celsius.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Min,
+    Max,
+    Avg,
+    Count,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Hasher {
+    #[default]
+    Fx,
+    /// `ahash::RandomState` with fixed seeds, so runs are reproducible.
+    #[cfg(feature = "ahash")]
+    Ahash,
+}
+
+/// How the merged cities are put in name order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortMode {
+    /// Insert every city into a `BTreeMap`.
+    #[default]
+    BTree,
+    /// `sort_unstable` the merged entries once.
+    Vec,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Args {
+    /// Input files, aggregated as if they were concatenated.
+    pub paths: Vec<String>,
+    /// Worker threads to spawn; `None` picks a default from the available
+    /// parallelism.
+    pub threads: Option<usize>,
+    pub chunk_size: usize,
+    pub huge_pages: bool,
+    pub io_uring: bool,
+    pub no_mmap: bool,
+    pub gzip: bool,
+    pub skip_header: bool,
+    pub allow_comments: bool,
+    pub delimiter: u8,
+    pub validate_range: bool,
+    pub output: Option<String>,
+    pub output_buffer_size: usize,
+    pub format: Format,
+    /// Ends every city of the default format with a newline instead of
+    /// separating them with a comma.
+    pub one_per_line: bool,
+    pub no_color: bool,
+    pub unit: Unit,
+    /// `None` prints min, avg and max.
+    pub select: Option<Vec<Column>>,
+    /// `None` keeps the alphabetical order from `sort_mode`.
+    pub sort_by: Option<SortKey>,
+    pub sort_mode: SortMode,
+    pub top_n: Option<usize>,
+    pub filter: Option<String>,
+    pub exclude: Option<String>,
+    pub min_count: Option<usize>,
+    pub hasher: Hasher,
+    pub lock_free: bool,
+    pub line_count: bool,
+    pub unique_cities: bool,
+    pub check: bool,
+    pub no_timing: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+    pub summary: bool,
+    pub count: bool,
+    pub median: bool,
+    /// Percentiles from 0 to 100 to append, in the order given.
+    pub percentiles: Vec<u8>,
+    pub stddev: bool,
+    pub ascii_histogram: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    MissingValue(&'static str),
+    InvalidValue {
+        flag: &'static str,
+        value: String,
+    },
+    TooSmall {
+        flag: &'static str,
+        value: usize,
+        min: usize,
+    },
+    Conflict(&'static str, &'static str),
+    Required(&'static str),
+    UnknownFlag(String),
+    UnexpectedArgument(String),
+}
+
+impl Display for ArgsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::MissingValue(flag) => write!(f, "{flag} requires a value"),
+            ArgsError::InvalidValue { flag, value } => {
+                write!(f, "invalid value '{value}' for {flag}")
+            }
+            ArgsError::TooSmall { flag, value, min } => {
+                write!(f, "{flag} must be at least {min}, got {value}")
+            }
+            ArgsError::Conflict(flag, other) => {
+                write!(f, "{flag} cannot be combined with {other}")
+            }
+            ArgsError::Required(flag) => write!(f, "{flag} is required"),
+            ArgsError::UnknownFlag(flag) => write!(f, "unknown flag '{flag}'"),
+            ArgsError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{arg}'"),
+        }
+    }
+}
+
+impl Args {
+    /// Parses the command line arguments, excluding the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, ArgsError> {
+        let mut paths = vec![];
+        let mut threads = None;
+        let mut chunk_size = DEFAULT_CHUNK_SIZE;
+        let mut huge_pages = false;
+        let mut io_uring = false;
+        let mut no_mmap = false;
+        let mut gzip = false;
+        let mut skip_header = false;
+        let mut allow_comments = false;
+        let mut delimiter = b';';
+        let mut validate_range = false;
+        let mut output = None;
+        let mut output_buffer_size = DEFAULT_OUTPUT_BUFFER_SIZE;
+        let mut format = Format::default();
+        let mut one_per_line = false;
+        let mut no_color = false;
+        let mut unit = Unit::default();
+        let mut select = None;
+        let mut sort_by = None;
+        let mut sort_mode = SortMode::default();
+        let mut top_n = None;
+        let mut filter = None;
+        let mut exclude = None;
+        let mut min_count = None;
+        let mut hasher = None;
+        let mut lock_free = false;
+        let mut line_count = false;
+        let mut unique_cities = false;
+        let mut check = false;
+        let mut no_timing = false;
+        let mut dry_run = false;
+        let mut verbose = false;
+        let mut summary = false;
+        let mut count = false;
+        let mut median = false;
+        let mut percentiles = vec![];
+        let mut stddev = false;
+        let mut ascii_histogram = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--threads" => threads = Some(parse_at_least(&mut args, "--threads", 1)?),
+                "--chunk-size" => {
+                    chunk_size = parse_at_least(&mut args, "--chunk-size", MIN_CHUNK_SIZE)?
+                }
+                "--huge-pages" if cfg!(target_os = "linux") => huge_pages = true,
+                "--io-uring" if cfg!(all(feature = "io-uring", target_os = "linux")) => {
+                    io_uring = true
+                }
+                "--no-mmap" => no_mmap = true,
+                "--gzip" if cfg!(feature = "gzip") => gzip = true,
+                "--skip-header" => skip_header = true,
+                "--allow-comments" => allow_comments = true,
+                "--delimiter" => {
+                    let value: String = parse_value(&mut args, "--delimiter")?;
+                    delimiter = match value.as_bytes() {
+                        b"\\t" => b'\t',
+                        // Anything that may be part of a temperature or end a
+                        // row would be ambiguous.
+                        &[b] if b.is_ascii()
+                            && !matches!(b, b'\n' | b'\r' | b'-' | b'.' | b'0'..=b'9') =>
+                        {
+                            b
+                        }
+                        _ => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--delimiter",
+                                value,
+                            })
+                        }
+                    };
+                }
+                "--validate-range" => validate_range = true,
+                "--output" => output = Some(parse_value(&mut args, "--output")?),
+                "--output-buffer-size" => {
+                    output_buffer_size = parse_value(&mut args, "--output-buffer-size")?
+                }
+                "--format" => {
+                    let value: String = parse_value(&mut args, "--format")?;
+                    format = match value.as_str() {
+                        "default" => Format::Default,
+                        "json" => Format::Json,
+                        "ndjson" => Format::Ndjson,
+                        "csv" => Format::Csv,
+                        "tsv" => Format::Tsv,
+                        "prometheus" => Format::Prometheus,
+                        "table" => Format::Table,
+                        "raw" => Format::Raw,
+                        _ => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--format",
+                                value,
+                            })
+                        }
+                    };
+                }
+                "--one-per-line" => one_per_line = true,
+                "--no-color" => no_color = true,
+                "--fahrenheit" => unit = Unit::Fahrenheit,
+                "--select" => {
+                    let value: String = parse_value(&mut args, "--select")?;
+                    select = match parse_select(&value) {
+                        Some(columns) => Some(columns),
+                        None => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--select",
+                                value,
+                            })
+                        }
+                    };
+                }
+                "--sort-by" => {
+                    let value: String = parse_value(&mut args, "--sort-by")?;
+                    sort_by = Some(match value.as_str() {
+                        "name" => SortKey::Name,
+                        "min" => SortKey::Min,
+                        "max" => SortKey::Max,
+                        "avg" => SortKey::Avg,
+                        "count" => SortKey::Count,
+                        _ => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--sort-by",
+                                value,
+                            })
+                        }
+                    });
+                }
+                "--sort-mode" => {
+                    let value: String = parse_value(&mut args, "--sort-mode")?;
+                    sort_mode = match value.as_str() {
+                        "btree" => SortMode::BTree,
+                        "vec" => SortMode::Vec,
+                        _ => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--sort-mode",
+                                value,
+                            })
+                        }
+                    };
+                }
+                "--top-n" => top_n = Some(parse_at_least(&mut args, "--top-n", 1)?),
+                "--filter" => filter = Some(parse_value(&mut args, "--filter")?),
+                "--exclude" => exclude = Some(parse_value(&mut args, "--exclude")?),
+                "--min-count" => min_count = Some(parse_value(&mut args, "--min-count")?),
+                "--hasher" => {
+                    let value: String = parse_value(&mut args, "--hasher")?;
+                    hasher = Some(match value.as_str() {
+                        "fx" => Hasher::Fx,
+                        #[cfg(feature = "ahash")]
+                        "ahash" => Hasher::Ahash,
+                        _ => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--hasher",
+                                value,
+                            })
+                        }
+                    });
+                }
+                "--lock-free" if cfg!(feature = "lock-free") => lock_free = true,
+                "--line-count" => line_count = true,
+                "--unique-cities" => unique_cities = true,
+                "--check" => check = true,
+                "--no-timing" => no_timing = true,
+                "--dry-run" => dry_run = true,
+                "--verbose" => verbose = true,
+                "--summary" => summary = true,
+                "--count" => count = true,
+                "--median" => median = true,
+                "--percentiles" => {
+                    let value: String = parse_value(&mut args, "--percentiles")?;
+                    percentiles = match parse_percentiles(&value) {
+                        Some(percentiles) => percentiles,
+                        None => {
+                            return Err(ArgsError::InvalidValue {
+                                flag: "--percentiles",
+                                value,
+                            })
+                        }
+                    };
+                }
+                "--stddev" => stddev = true,
+                "--ascii-histogram" => ascii_histogram = true,
+                flag if flag.starts_with("--") => {
+                    return Err(ArgsError::UnknownFlag(arg));
+                }
+                _ => paths.push(arg),
+            }
+        }
+
+        if paths.is_empty() {
+            paths.push(DEFAULT_PATH.to_string());
+        }
+        if lock_free && hasher.is_some() {
+            return Err(ArgsError::Conflict("--lock-free", "--hasher"));
+        }
+        if gzip && lock_free {
+            return Err(ArgsError::Conflict("--gzip", "--lock-free"));
+        }
+        if lock_free && median {
+            return Err(ArgsError::Conflict("--lock-free", "--median"));
+        }
+        if lock_free && !percentiles.is_empty() {
+            return Err(ArgsError::Conflict("--lock-free", "--percentiles"));
+        }
+        if lock_free && ascii_histogram {
+            return Err(ArgsError::Conflict("--lock-free", "--ascii-histogram"));
+        }
+        if gzip && line_count {
+            return Err(ArgsError::Conflict("--gzip", "--line-count"));
+        }
+        if gzip && unique_cities {
+            return Err(ArgsError::Conflict("--gzip", "--unique-cities"));
+        }
+        if gzip && check {
+            return Err(ArgsError::Conflict("--gzip", "--check"));
+        }
+        if one_per_line && format != Format::Default {
+            return Err(ArgsError::Conflict("--one-per-line", "--format"));
+        }
+
+        Ok(Args {
+            paths,
+            threads,
+            chunk_size,
+            huge_pages,
+            io_uring,
+            no_mmap,
+            gzip,
+            skip_header,
+            allow_comments,
+            delimiter,
+            validate_range,
+            output,
+            output_buffer_size,
+            format,
+            one_per_line,
+            no_color,
+            unit,
+            select,
+            sort_by,
+            sort_mode,
+            top_n,
+            filter,
+            exclude,
+            min_count,
+            hasher: hasher.unwrap_or_default(),
+            lock_free,
+            line_count,
+            unique_cities,
+            check,
+            no_timing,
+            dry_run,
+            verbose,
+            summary,
+            count,
+            median,
+            percentiles,
+            stddev,
+            ascii_histogram,
+        })
+    }
+
+    /// Whether to color the results: only the default format, only on a
+    /// terminal, and neither with --no-color nor with a non-empty
+    /// `NO_COLOR`, as per no-color.org.
+    pub fn color(&self, stdout_is_terminal: bool, no_color_env: Option<&OsStr>) -> bool {
+        let no_color_env = no_color_env.is_some_and(|value| !value.is_empty());
+        self.format == Format::Default
+            && self.output.is_none()
+            && stdout_is_terminal
+            && !self.no_color
+            && !no_color_env
+    }
+
+    /// The per-city data to keep beyond min, max, sum and count.
+    pub fn extras(&self) -> Extras {
+        Extras {
+            sample: self.median || !self.percentiles.is_empty(),
+            histogram: self.ascii_histogram,
+        }
+    }
+
+    /// The options that change how rows are parsed.
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            allow_comments: self.allow_comments,
+            delimiter: self.delimiter,
+            validate_range: self.validate_range,
+            first_line: if self.skip_header { 2 } else { 1 },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct GenerateArgs {
+    pub rows: usize,
+    pub cities: usize,
+    pub output: String,
+    /// `None` seeds the generator randomly.
+    pub seed: Option<u64>,
+}
+
+impl GenerateArgs {
+    /// Parses the arguments following `generate`.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<GenerateArgs, ArgsError> {
+        let mut rows = None;
+        let mut cities = None;
+        let mut output = None;
+        let mut seed = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--rows" => rows = Some(parse_value(&mut args, "--rows")?),
+                "--cities" => cities = Some(parse_at_least(&mut args, "--cities", 1)?),
+                "--output" => output = Some(parse_value(&mut args, "--output")?),
+                "--seed" => seed = Some(parse_value(&mut args, "--seed")?),
+                flag if flag.starts_with("--") => return Err(ArgsError::UnknownFlag(arg)),
+                _ => return Err(ArgsError::UnexpectedArgument(arg)),
+            }
+        }
+
+        Ok(GenerateArgs {
+            rows: rows.ok_or(ArgsError::Required("--rows"))?,
+            cities: cities.ok_or(ArgsError::Required("--cities"))?,
+            output: output.ok_or(ArgsError::Required("--output"))?,
+            seed,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ValidateArgs {
+    pub input: String,
+    pub expected: String,
+}
+
+impl ValidateArgs {
+    /// Parses the arguments following `validate`.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<ValidateArgs, ArgsError> {
+        let mut input = None;
+        let mut expected = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--input" => input = Some(parse_value(&mut args, "--input")?),
+                "--expected" => expected = Some(parse_value(&mut args, "--expected")?),
+                flag if flag.starts_with("--") => return Err(ArgsError::UnknownFlag(arg)),
+                _ => return Err(ArgsError::UnexpectedArgument(arg)),
+            }
+        }
+
+        Ok(ValidateArgs {
+            input: input.ok_or(ArgsError::Required("--input"))?,
+            expected: expected.ok_or(ArgsError::Required("--expected"))?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MergeArgs {
+    /// Raw results, merged in any order.
+    pub paths: Vec<String>,
+}
+
+impl MergeArgs {
+    /// Parses the arguments following `merge`.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<MergeArgs, ArgsError> {
+        let mut raw = false;
+        let mut paths = vec![];
+
+        for arg in args {
+            match arg.as_str() {
+                "--raw" => raw = true,
+                flag if flag.starts_with("--") => return Err(ArgsError::UnknownFlag(arg)),
+                _ => paths.push(arg),
+            }
+        }
+
+        if !raw {
+            return Err(ArgsError::Required("--raw"));
+        }
+        if paths.is_empty() {
+            return Err(ArgsError::Required("FILE"));
+        }
+        Ok(MergeArgs { paths })
+    }
+}
+
+/// The columns named in `value`, or `None` if it names an unknown one or
+/// the same one twice.
+fn parse_select(value: &str) -> Option<Vec<Column>> {
+    let mut columns = vec![];
+    for name in value.split(',') {
+        let column = match name {
+            "min" => Column::Min,
+            "avg" => Column::Avg,
+            "max" => Column::Max,
+            "count" => Column::Count,
+            "sum" => Column::Sum,
+            _ => return None,
+        };
+        if columns.contains(&column) {
+            return None;
+        }
+        columns.push(column);
+    }
+    Some(columns)
+}
+
+/// Parses `p50,p90,p99` into the percentiles, each from 0 to 100 and given
+/// at most once.
+fn parse_percentiles(value: &str) -> Option<Vec<u8>> {
+    let mut percentiles = vec![];
+    for name in value.split(',') {
+        let percentile: u8 = name.strip_prefix('p')?.parse().ok()?;
+        if percentile > 100 || percentiles.contains(&percentile) {
+            return None;
+        }
+        percentiles.push(percentile);
+    }
+    Some(percentiles)
+}
+
+fn parse_value<T: std::str::FromStr, I: Iterator<Item = String>>(
+    args: &mut I,
+    flag: &'static str,
+) -> Result<T, ArgsError> {
+    let value = args.next().ok_or(ArgsError::MissingValue(flag))?;
+    value
+        .parse()
+        .map_err(|_| ArgsError::InvalidValue { flag, value })
+}
+
+fn parse_at_least<I: Iterator<Item = String>>(
+    args: &mut I,
+    flag: &'static str,
+    min: usize,
+) -> Result<usize, ArgsError> {
+    let value = parse_value(args, flag)?;
+    if value < min {
+        return Err(ArgsError::TooSmall { flag, value, min });
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        args::{
+            Args, ArgsError, Format, GenerateArgs, Hasher, MergeArgs, SortKey, SortMode, Unit,
+            ValidateArgs,
+        },
+        output::Column,
+        Extras,
+    };
+    use pretty_assertions::assert_eq;
+    use std::ffi::OsStr;
+
+    fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// The arguments of a run without any flags.
+    fn defaults() -> Args {
+        Args {
+            paths: vec!["measurements.txt".to_string()],
+            threads: None,
+            chunk_size: 40_000,
+            huge_pages: false,
+            io_uring: false,
+            no_mmap: false,
+            gzip: false,
+            skip_header: false,
+            allow_comments: false,
+            delimiter: b';',
+            validate_range: false,
+            output: None,
+            output_buffer_size: 65_536,
+            format: Format::Default,
+            one_per_line: false,
+            no_color: false,
+            unit: Unit::Celsius,
+            select: None,
+            sort_by: None,
+            sort_mode: SortMode::BTree,
+            top_n: None,
+            filter: None,
+            exclude: None,
+            min_count: None,
+            hasher: Hasher::Fx,
+            lock_free: false,
+            line_count: false,
+            unique_cities: false,
+            check: false,
+            no_timing: false,
+            dry_run: false,
+            verbose: false,
+            summary: false,
+            count: false,
+            median: false,
+            percentiles: vec![],
+            stddev: false,
+            ascii_histogram: false,
+        }
+    }
+
+    #[test]
+    fn it_parses_defaults() {
+        assert_eq!(Ok(defaults()), parse(&[]));
+    }
+
+    #[test]
+    fn it_parses_threads() {
+        assert_eq!(
+            Ok(Args {
+                paths: vec!["data.txt".to_string()],
+                threads: Some(4),
+                ..defaults()
+            }),
+            parse(&["--threads", "4", "data.txt"])
+        );
+        assert_eq!(
+            Err(ArgsError::MissingValue("--threads")),
+            parse(&["--threads"])
+        );
+        assert_eq!(
+            Err(ArgsError::TooSmall {
+                flag: "--threads",
+                value: 0,
+                min: 1
+            }),
+            parse(&["--threads", "0"])
+        );
+        assert_eq!(
+            Err(ArgsError::InvalidValue {
+                flag: "--threads",
+                value: "x".to_string()
+            }),
+            parse(&["--threads", "x"])
+        );
+    }
+
+    #[test]
+    fn it_parses_chunk_size() {
+        assert_eq!(
+            Ok(Args {
+                threads: Some(2),
+                chunk_size: 128,
+                ..defaults()
+            }),
+            parse(&["--chunk-size", "128", "--threads", "2"])
+        );
+        assert_eq!(
+            Err(ArgsError::TooSmall {
+                flag: "--chunk-size",
+                value: 127,
+                min: 128
+            }),
+            parse(&["--chunk-size", "127"])
+        );
+    }
+
+    #[test]
+    fn it_parses_huge_pages() {
+        assert!(!parse(&[]).unwrap().huge_pages);
+        #[cfg(target_os = "linux")]
+        assert!(parse(&["--huge-pages"]).unwrap().huge_pages);
+    }
+
+    #[test]
+    fn it_parses_io_uring() {
+        assert!(!parse(&[]).unwrap().io_uring);
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        assert!(parse(&["--io-uring"]).unwrap().io_uring);
+        #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+        assert_eq!(
+            Err(ArgsError::UnknownFlag("--io-uring".to_string())),
+            parse(&["--io-uring"])
+        );
+    }
+
+    #[test]
+    fn it_parses_no_mmap() {
+        assert!(!parse(&[]).unwrap().no_mmap);
+        assert!(parse(&["--no-mmap"]).unwrap().no_mmap);
+    }
+
+    #[test]
+    fn it_parses_output() {
+        assert_eq!(
+            Some("out.txt".to_string()),
+            parse(&["--output", "out.txt"]).unwrap().output
+        );
+    }
+
+    #[test]
+    fn it_parses_one_per_line() {
+        assert!(!parse(&[]).unwrap().one_per_line);
+        assert!(parse(&["--one-per-line"]).unwrap().one_per_line);
+        assert!(
+            parse(&["--format", "default", "--one-per-line"])
+                .unwrap()
+                .one_per_line
+        );
+        assert_eq!(
+            Err(ArgsError::Conflict("--one-per-line", "--format")),
+            parse(&["--one-per-line", "--format", "csv"])
+        );
+    }
+
+    #[test]
+    fn it_parses_no_color() {
+        let args = parse(&[]).unwrap();
+        assert!(!args.no_color);
+        assert!(args.color(true, None));
+        assert!(args.color(true, Some(OsStr::new(""))));
+        assert!(!args.color(false, None));
+        assert!(!args.color(true, Some(OsStr::new("1"))));
+
+        assert!(!parse(&["--no-color"]).unwrap().color(true, None));
+        assert!(!parse(&["--format", "csv"]).unwrap().color(true, None));
+        assert!(!parse(&["--output", "out.txt"]).unwrap().color(true, None));
+        assert!(parse(&["--one-per-line"]).unwrap().color(true, None));
+    }
+
+    #[test]
+    fn it_parses_fahrenheit() {
+        assert_eq!(Unit::Celsius, parse(&[]).unwrap().unit);
+        assert_eq!(Unit::Fahrenheit, parse(&["--fahrenheit"]).unwrap().unit);
+    }
+
+    #[test]
+    fn it_parses_output_buffer_size() {
+        assert_eq!(65_536, parse(&[]).unwrap().output_buffer_size);
+        assert_eq!(
+            0,
+            parse(&["--output-buffer-size", "0"])
+                .unwrap()
+                .output_buffer_size
+        );
+    }
+
+    #[test]
+    fn it_parses_format() {
+        assert_eq!(Format::Json, parse(&["--format", "json"]).unwrap().format);
+        assert_eq!(
+            Format::Ndjson,
+            parse(&["--format", "ndjson"]).unwrap().format
+        );
+        assert_eq!(Format::Csv, parse(&["--format", "csv"]).unwrap().format);
+        assert_eq!(Format::Tsv, parse(&["--format", "tsv"]).unwrap().format);
+        assert_eq!(
+            Format::Prometheus,
+            parse(&["--format", "prometheus"]).unwrap().format
+        );
+        assert_eq!(Format::Table, parse(&["--format", "table"]).unwrap().format);
+        assert_eq!(Format::Raw, parse(&["--format", "raw"]).unwrap().format);
+        assert_eq!(
+            Format::Default,
+            parse(&["--format", "default"]).unwrap().format
+        );
+        assert_eq!(
+            Err(ArgsError::InvalidValue {
+                flag: "--format",
+                value: "xml".to_string()
+            }),
+            parse(&["--format", "xml"])
+        );
+    }
+
+    #[test]
+    fn it_parses_select() {
+        assert_eq!(None, parse(&[]).unwrap().select);
+        assert_eq!(
+            Some(vec![Column::Max, Column::Min]),
+            parse(&["--select", "max,min"]).unwrap().select
+        );
+        assert_eq!(
+            Some(vec![
+                Column::Min,
+                Column::Avg,
+                Column::Max,
+                Column::Count,
+                Column::Sum
+            ]),
+            parse(&["--select", "min,avg,max,count,sum"])
+                .unwrap()
+                .select
+        );
+        for value in ["", "min,", "median", "min,MAX", "avg,avg"] {
+            assert_eq!(
+                Err(ArgsError::InvalidValue {
+                    flag: "--select",
+                    value: value.to_string()
+                }),
+                parse(&["--select", value])
+            );
+        }
+    }
+
+    #[test]
+    fn it_parses_sort_by() {
+        for (value, key) in [
+            ("name", SortKey::Name),
+            ("min", SortKey::Min),
+            ("max", SortKey::Max),
+            ("avg", SortKey::Avg),
+            ("count", SortKey::Count),
+        ] {
+            assert_eq!(Some(key), parse(&["--sort-by", value]).unwrap().sort_by);
+        }
+        assert!(parse(&["--sort-by", "median"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_sort_mode() {
+        assert_eq!(SortMode::BTree, parse(&[]).unwrap().sort_mode);
+        assert_eq!(
+            SortMode::Vec,
+            parse(&["--sort-mode", "vec"]).unwrap().sort_mode
+        );
+        assert_eq!(
+            Err(ArgsError::InvalidValue {
+                flag: "--sort-mode",
+                value: "heap".to_string()
+            }),
+            parse(&["--sort-mode", "heap"])
+        );
+    }
+
+    #[test]
+    fn it_parses_top_n() {
+        assert_eq!(Some(3), parse(&["--top-n", "3"]).unwrap().top_n);
+        assert!(parse(&["--top-n", "0"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_filter() {
+        assert_eq!(
+            Some("Is".to_string()),
+            parse(&["--filter", "Is"]).unwrap().filter
+        );
+        assert_eq!(
+            Err(ArgsError::MissingValue("--filter")),
+            parse(&["--filter"])
+        );
+    }
+
+    #[test]
+    fn it_parses_exclude() {
+        let args = parse(&["--filter", "A", "--exclude", "AB"]).unwrap();
+        assert_eq!(Some("A".to_string()), args.filter);
+        assert_eq!(Some("AB".to_string()), args.exclude);
+    }
+
+    #[test]
+    fn it_parses_min_count() {
+        assert_eq!(None, parse(&[]).unwrap().min_count);
+        assert_eq!(Some(2), parse(&["--min-count", "2"]).unwrap().min_count);
+    }
+
+    #[test]
+    fn it_parses_hasher() {
+        assert_eq!(Hasher::Fx, parse(&["--hasher", "fx"]).unwrap().hasher);
+        #[cfg(feature = "ahash")]
+        assert_eq!(Hasher::Ahash, parse(&["--hasher", "ahash"]).unwrap().hasher);
+        #[cfg(not(feature = "ahash"))]
+        assert_eq!(
+            Err(ArgsError::InvalidValue {
+                flag: "--hasher",
+                value: "ahash".to_string()
+            }),
+            parse(&["--hasher", "ahash"])
+        );
+    }
+
+    #[test]
+    fn it_parses_gzip() {
+        assert!(!parse(&[]).unwrap().gzip);
+        #[cfg(feature = "gzip")]
+        {
+            assert!(parse(&["--gzip"]).unwrap().gzip);
+            assert_eq!(
+                Err(ArgsError::Conflict("--gzip", "--line-count")),
+                parse(&["--gzip", "--line-count"])
+            );
+            assert_eq!(
+                Err(ArgsError::Conflict("--gzip", "--unique-cities")),
+                parse(&["--unique-cities", "--gzip"])
+            );
+            assert_eq!(
+                Err(ArgsError::Conflict("--gzip", "--check")),
+                parse(&["--gzip", "--check"])
+            );
+        }
+        #[cfg(not(feature = "gzip"))]
+        assert_eq!(
+            Err(ArgsError::UnknownFlag("--gzip".to_string())),
+            parse(&["--gzip"])
+        );
+    }
+
+    #[test]
+    fn it_parses_skip_header() {
+        assert!(!parse(&[]).unwrap().skip_header);
+        assert!(parse(&["--skip-header"]).unwrap().skip_header);
+    }
+
+    #[test]
+    fn it_parses_allow_comments() {
+        assert!(!parse(&[]).unwrap().parse_options().allow_comments);
+        assert!(
+            parse(&["--allow-comments"])
+                .unwrap()
+                .parse_options()
+                .allow_comments
+        );
+    }
+
+    #[test]
+    fn it_parses_delimiter() {
+        assert_eq!(b';', parse(&[]).unwrap().delimiter);
+        assert_eq!(b'|', parse(&["--delimiter", "|"]).unwrap().delimiter);
+        assert_eq!(b'\t', parse(&["--delimiter", "\t"]).unwrap().delimiter);
+        assert_eq!(b'\t', parse(&["--delimiter", "\\t"]).unwrap().delimiter);
+        for value in ["", "||", "-", "5", "\n", "é"] {
+            assert_eq!(
+                Err(ArgsError::InvalidValue {
+                    flag: "--delimiter",
+                    value: value.to_string()
+                }),
+                parse(&["--delimiter", value])
+            );
+        }
+    }
+
+    #[test]
+    fn it_parses_validate_range() {
+        let options = parse(&[]).unwrap().parse_options();
+        assert!(!options.validate_range);
+        assert_eq!(1, options.first_line);
+        let options = parse(&["--validate-range", "--skip-header"])
+            .unwrap()
+            .parse_options();
+        assert!(options.validate_range);
+        assert_eq!(2, options.first_line);
+    }
+
+    #[test]
+    fn it_parses_lock_free() {
+        assert!(!parse(&[]).unwrap().lock_free);
+        #[cfg(feature = "lock-free")]
+        {
+            assert!(parse(&["--lock-free"]).unwrap().lock_free);
+            assert_eq!(
+                Err(ArgsError::Conflict("--lock-free", "--hasher")),
+                parse(&["--lock-free", "--hasher", "fx"])
+            );
+            #[cfg(feature = "gzip")]
+            assert_eq!(
+                Err(ArgsError::Conflict("--gzip", "--lock-free")),
+                parse(&["--gzip", "--lock-free"])
+            );
+            assert_eq!(
+                Err(ArgsError::Conflict("--lock-free", "--median")),
+                parse(&["--median", "--lock-free"])
+            );
+            assert_eq!(
+                Err(ArgsError::Conflict("--lock-free", "--percentiles")),
+                parse(&["--percentiles", "p90", "--lock-free"])
+            );
+            assert_eq!(
+                Err(ArgsError::Conflict("--lock-free", "--ascii-histogram")),
+                parse(&["--lock-free", "--ascii-histogram"])
+            );
+        }
+        #[cfg(not(feature = "lock-free"))]
+        assert_eq!(
+            Err(ArgsError::UnknownFlag("--lock-free".to_string())),
+            parse(&["--lock-free"])
+        );
+    }
+
+    #[test]
+    fn it_parses_line_count() {
+        assert!(!parse(&[]).unwrap().line_count);
+        assert!(parse(&["--line-count"]).unwrap().line_count);
+    }
+
+    #[test]
+    fn it_parses_unique_cities() {
+        assert!(!parse(&[]).unwrap().unique_cities);
+        assert!(parse(&["--unique-cities"]).unwrap().unique_cities);
+    }
+
+    #[test]
+    fn it_parses_check() {
+        assert!(!parse(&[]).unwrap().check);
+        assert!(parse(&["--check"]).unwrap().check);
+    }
+
+    #[test]
+    fn it_parses_no_timing() {
+        assert!(!parse(&[]).unwrap().no_timing);
+        assert!(parse(&["--no-timing"]).unwrap().no_timing);
+    }
+
+    #[test]
+    fn it_parses_dry_run() {
+        assert!(!parse(&[]).unwrap().dry_run);
+        assert!(parse(&["--dry-run"]).unwrap().dry_run);
+    }
+
+    #[test]
+    fn it_parses_verbose() {
+        assert!(!parse(&[]).unwrap().verbose);
+        assert!(parse(&["--verbose"]).unwrap().verbose);
+    }
+
+    #[test]
+    fn it_parses_summary() {
+        assert!(!parse(&[]).unwrap().summary);
+        assert!(parse(&["--summary"]).unwrap().summary);
+    }
+
+    #[test]
+    fn it_parses_count() {
+        assert!(!parse(&[]).unwrap().count);
+        assert!(parse(&["--count"]).unwrap().count);
+    }
+
+    #[test]
+    fn it_parses_median() {
+        assert!(!parse(&[]).unwrap().median);
+        assert!(parse(&["--median"]).unwrap().median);
+    }
+
+    #[test]
+    fn it_parses_percentiles() {
+        assert_eq!(Vec::<u8>::new(), parse(&[]).unwrap().percentiles);
+        let args = parse(&["--percentiles", "p50,p90,p99,p0,p100"]).unwrap();
+        assert_eq!(vec![50, 90, 99, 0, 100], args.percentiles);
+        assert!(args.extras().sample);
+        for value in ["", "50", "p50,", "p101", "p-1", "p9.5", "p50,p50", "P50"] {
+            assert_eq!(
+                Err(ArgsError::InvalidValue {
+                    flag: "--percentiles",
+                    value: value.to_string()
+                }),
+                parse(&["--percentiles", value])
+            );
+        }
+    }
+
+    #[test]
+    fn it_parses_stddev() {
+        assert!(!parse(&[]).unwrap().stddev);
+        assert!(parse(&["--stddev"]).unwrap().stddev);
+    }
+
+    #[test]
+    fn it_parses_ascii_histogram() {
+        assert_eq!(Extras::default(), parse(&[]).unwrap().extras());
+        assert_eq!(
+            Extras {
+                sample: true,
+                histogram: true
+            },
+            parse(&["--ascii-histogram", "--median"]).unwrap().extras()
+        );
+    }
+
+    #[test]
+    fn it_rejects_unknown_arguments() {
+        assert_eq!(
+            Err(ArgsError::UnknownFlag("--nope".to_string())),
+            parse(&["--nope"])
+        );
+    }
+
+    #[test]
+    fn it_parses_several_paths() {
+        assert_eq!(
+            vec!["a.txt".to_string(), "b.txt".to_string()],
+            parse(&["a.txt", "--count", "b.txt"]).unwrap().paths
+        );
+    }
+
+    fn parse_generate(args: &[&str]) -> Result<GenerateArgs, ArgsError> {
+        GenerateArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_parses_generate() {
+        assert_eq!(
+            Ok(GenerateArgs {
+                rows: 1000,
+                cities: 10,
+                output: "out.txt".to_string(),
+                seed: Some(42),
+            }),
+            parse_generate(&[
+                "--rows", "1000", "--cities", "10", "--output", "out.txt", "--seed", "42"
+            ])
+        );
+        assert_eq!(
+            None,
+            parse_generate(&["--rows", "1", "--cities", "1", "--output", "a"])
+                .unwrap()
+                .seed
+        );
+        assert_eq!(
+            Err(ArgsError::Required("--cities")),
+            parse_generate(&["--rows", "1", "--output", "a"])
+        );
+        assert_eq!(
+            Err(ArgsError::TooSmall {
+                flag: "--cities",
+                value: 0,
+                min: 1
+            }),
+            parse_generate(&["--cities", "0"])
+        );
+        assert_eq!(
+            Err(ArgsError::UnexpectedArgument("a.txt".to_string())),
+            parse_generate(&["a.txt"])
+        );
+    }
+
+    #[test]
+    fn it_parses_validate() {
+        assert_eq!(
+            Ok(ValidateArgs {
+                input: "in.txt".to_string(),
+                expected: "ref.txt".to_string(),
+            }),
+            ValidateArgs::parse(["--expected", "ref.txt", "--input", "in.txt"].map(String::from))
+        );
+        assert_eq!(
+            Err(ArgsError::Required("--expected")),
+            ValidateArgs::parse(["--input", "in.txt"].map(String::from))
+        );
+    }
+
+    #[test]
+    fn it_parses_merge() {
+        assert_eq!(
+            Ok(MergeArgs {
+                paths: vec!["a.raw".to_string(), "b.raw".to_string()],
+            }),
+            MergeArgs::parse(["a.raw", "--raw", "b.raw"].map(String::from))
+        );
+        assert_eq!(
+            Err(ArgsError::Required("--raw")),
+            MergeArgs::parse(["a.raw"].map(String::from))
+        );
+        assert_eq!(
+            Err(ArgsError::Required("FILE")),
+            MergeArgs::parse(["--raw"].map(String::from))
+        );
+    }
+}
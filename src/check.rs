@@ -0,0 +1,120 @@
+use crate::{is_out_of_range, parse_next_row, ParseError, ParseOptions};
+use std::fmt::{self, Display, Formatter};
+
+/// Why a line would stop an aggregation run.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LineError {
+    Parse(ParseError),
+    InvalidUtf8,
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::Parse(err) => write!(f, "{err}"),
+            LineError::InvalidUtf8 => write!(f, "city name is not UTF-8"),
+        }
+    }
+}
+
+/// Parses every line of `buffer` without aggregating it, calling `on_error`
+/// with the number and error of each malformed one instead of stopping at
+/// the first. Returns the number of lines checked, comments included.
+pub fn check_lines(
+    buffer: &[u8],
+    options: ParseOptions,
+    mut on_error: impl FnMut(u64, LineError),
+) -> u64 {
+    if buffer.is_empty() {
+        return 0;
+    }
+    // A final newline ends the last line rather than starting an empty one.
+    let lines = buffer.strip_suffix(b"\n").unwrap_or(buffer);
+    let mut checked = 0;
+    for (line, number) in lines.split(|&b| b == b'\n').zip(options.first_line..) {
+        checked += 1;
+        if options.allow_comments && line.first() == Some(&b'#') {
+            continue;
+        }
+        match parse_next_row(line, options.delimiter) {
+            Ok(row) if std::str::from_utf8(row.city).is_err() => {
+                on_error(number, LineError::InvalidUtf8)
+            }
+            Ok(_) => {}
+            // Warned about and skipped, as an aggregation run would.
+            Err(ParseError::InvalidTemperature)
+                if options.validate_range && is_out_of_range(line, options.delimiter, number) => {}
+            Err(err) => on_error(number, LineError::Parse(err)),
+        }
+    }
+    checked
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        check::{check_lines, LineError},
+        ParseError, ParseOptions,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn check(buffer: &[u8], options: ParseOptions) -> (u64, Vec<(u64, LineError)>) {
+        let mut errors = vec![];
+        let lines = check_lines(buffer, options, |line, err| errors.push((line, err)));
+        (lines, errors)
+    }
+
+    #[test]
+    fn it_reports_every_malformed_line() {
+        let buffer = b"Hamburg;12.0\nHamburg 12.0\nK\xf6ln;8.5\r\nOslo;1x.0\n\nOslo;1.0";
+        assert_eq!(
+            (
+                6,
+                vec![
+                    (2, LineError::Parse(ParseError::MissingSeparator)),
+                    (3, LineError::InvalidUtf8),
+                    (4, LineError::Parse(ParseError::InvalidTemperature)),
+                    (5, LineError::Parse(ParseError::RowTooShort)),
+                ]
+            ),
+            check(buffer, ParseOptions::default())
+        );
+        assert_eq!((0, vec![]), check(b"", ParseOptions::default()));
+        assert_eq!((1, vec![]), check(b"Oslo;1.0\n", ParseOptions::default()));
+    }
+
+    #[test]
+    fn it_follows_the_parse_options() {
+        let options = ParseOptions {
+            allow_comments: true,
+            delimiter: b'|',
+            first_line: 2,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            (3, vec![(4, LineError::Parse(ParseError::MissingSeparator))]),
+            check(b"# comment\nOslo|1.0\nOslo;1.0\n", options)
+        );
+    }
+
+    #[test]
+    fn it_skips_out_of_range_temperatures_when_validating() {
+        let buffer = b"Oslo;100.0\r\nOslo;1x.0\nOslo;1.0\n";
+        let invalid = (2, LineError::Parse(ParseError::InvalidTemperature));
+        let options = ParseOptions {
+            validate_range: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!((3, vec![invalid]), check(buffer, options));
+        assert_eq!(
+            (
+                3,
+                vec![
+                    (1, LineError::Parse(ParseError::InvalidTemperature)),
+                    (2, LineError::Parse(ParseError::InvalidTemperature)),
+                ]
+            ),
+            check(buffer, ParseOptions::default())
+        );
+    }
+}
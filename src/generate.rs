@@ -0,0 +1,95 @@
+use fastrand::Rng;
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+const MIN_NAME_LEN: usize = 5;
+const MAX_NAME_LEN: usize = 15;
+/// Largest absolute temperature, scaled by 10 like the parsed measurements.
+const MAX_MEASURE: i32 = 999;
+
+/// Writes `rows` measurements drawn uniformly from `cities` random names,
+/// with temperatures uniform in [-99.9, 99.9]. The same `seed` always
+/// produces the same output.
+pub fn generate<W: Write>(out: &mut W, rows: usize, cities: usize, seed: u64) -> io::Result<()> {
+    let mut rng = Rng::with_seed(seed);
+    let names = city_names(&mut rng, cities);
+    let mut row = Vec::with_capacity(MAX_NAME_LEN + ";-99.9\n".len());
+    for _ in 0..rows {
+        let measure = rng.i32(-MAX_MEASURE..=MAX_MEASURE);
+        row.clear();
+        row.extend_from_slice(names[rng.usize(..names.len())].as_bytes());
+        row.push(b';');
+        if measure < 0 {
+            row.push(b'-');
+        }
+        let measure = measure.unsigned_abs();
+        if measure >= 100 {
+            row.push(b'0' + (measure / 100) as u8);
+        }
+        row.push(b'0' + (measure / 10 % 10) as u8);
+        row.push(b'.');
+        row.push(b'0' + (measure % 10) as u8);
+        row.push(b'\n');
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
+
+/// `count` distinct ASCII names of 5 to 15 letters, capitalized.
+fn city_names(rng: &mut Rng, count: usize) -> Vec<String> {
+    let mut seen = HashSet::with_capacity(count);
+    let mut names = Vec::with_capacity(count);
+    while names.len() < count {
+        let len = rng.usize(MIN_NAME_LEN..=MAX_NAME_LEN);
+        let name: String = (0..len)
+            .map(|i| match i {
+                0 => rng.uppercase(),
+                _ => rng.lowercase(),
+            })
+            .collect();
+        if seen.insert(name.clone()) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{generate::generate, parse_next_row};
+    use pretty_assertions::assert_eq;
+    use std::collections::HashSet;
+
+    fn generated(rows: usize, cities: usize, seed: u64) -> Vec<u8> {
+        let mut out = vec![];
+        generate(&mut out, rows, cities, seed).unwrap();
+        out
+    }
+
+    #[test]
+    fn it_generates_parseable_rows() {
+        let out = generated(10_000, 20, 7);
+        let mut names = HashSet::new();
+        let mut rows = 0;
+        let mut i = 0;
+        while i < out.len() {
+            let row = parse_next_row(&out[i..], b';').unwrap();
+            assert!((5..=15).contains(&row.city.len()));
+            assert!(row.city.iter().all(u8::is_ascii_alphabetic));
+            assert!((-999..=999).contains(&row.temperature));
+            names.insert(row.city);
+            rows += 1;
+            i += row.bytes_consumed;
+        }
+        assert_eq!(10_000, rows);
+        assert_eq!(20, names.len());
+    }
+
+    #[test]
+    fn it_is_reproducible_with_a_seed() {
+        assert_eq!(generated(1000, 10, 42), generated(1000, 10, 42));
+        assert!(generated(1000, 10, 42) != generated(1000, 10, 43));
+    }
+}
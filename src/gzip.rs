@@ -0,0 +1,285 @@
+use crate::{
+    count_newlines, strip_bom, Extras, MeasurementIter, ParseError, ParseOptions, Stats, BOM,
+};
+use flate2::read::GzDecoder;
+use rustc_hash::FxHashMap;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+};
+
+/// Bytes decompressed before the complete rows among them are aggregated.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    /// A malformed row on line `line` of the stream.
+    Parse {
+        line: u64,
+        err: ParseError,
+    },
+    /// A city name that is not UTF-8, `offset` bytes into the stream.
+    InvalidUtf8 {
+        offset: u64,
+    },
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(err) => write!(f, "{err}"),
+            StreamError::Parse { line, err } => write!(f, "line {line}: {err}"),
+            StreamError::InvalidUtf8 { offset } => {
+                write!(f, "city name at byte {offset} is not UTF-8")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> Self {
+        StreamError::Io(err)
+    }
+}
+
+/// Decompresses `reader` and aggregates its rows into `cities_stats`,
+/// ignoring the first line if `skip_header` is set. Returns the number of
+/// decompressed bytes.
+pub fn aggregate_gzip<R: Read>(
+    reader: R,
+    cities_stats: &mut FxHashMap<Vec<u8>, Stats>,
+    extras: Extras,
+    options: ParseOptions,
+    skip_header: bool,
+) -> Result<u64, StreamError> {
+    let mut reader = BufReader::new(GzDecoder::new(reader));
+    let header = match skip_header {
+        true => reader.read_until(b'\n', &mut vec![])?,
+        false => 0,
+    };
+    let read = aggregate_stream(reader, cities_stats, extras, options)?;
+    Ok(header as u64 + read)
+}
+
+/// Aggregates the rows of `reader` into `cities_stats` a block at a time,
+/// for input that cannot be mapped. A row cut at the end of a block is
+/// carried over to the next one, so only the complete rows are parsed.
+/// Returns the number of bytes read.
+pub fn aggregate_stream<R: Read>(
+    mut reader: R,
+    cities_stats: &mut FxHashMap<Vec<u8>, Stats>,
+    extras: Extras,
+    options: ParseOptions,
+) -> Result<u64, StreamError> {
+    let mut buffer = Vec::with_capacity(2 * BLOCK_SIZE);
+    // Bytes of the stream before `buffer[0]`.
+    let mut offset = 0;
+    let mut first_line = options.first_line;
+    let mut at_start = true;
+    loop {
+        let len = buffer.len();
+        buffer.resize(len + BLOCK_SIZE, 0);
+        let read = match reader.read(&mut buffer[len..]) {
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {
+                buffer.truncate(len);
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        buffer.truncate(len + read);
+        if at_start {
+            if buffer.len() < BOM.len() && read > 0 {
+                continue;
+            }
+            let bom = buffer.len() - strip_bom(&buffer).len();
+            buffer.drain(..bom);
+            offset += bom as u64;
+            at_start = false;
+        }
+        let end = match read {
+            // The last row may lack its newline.
+            0 => buffer.len(),
+            _ => match buffer.iter().rposition(|&b| b == b'\n') {
+                Some(newline) => newline + 1,
+                None => continue,
+            },
+        };
+        let rows = &buffer[..end];
+        let options = ParseOptions {
+            first_line,
+            ..options
+        };
+        aggregate_rows(rows, offset, cities_stats, extras, options)?;
+        first_line += count_newlines(rows);
+        buffer.drain(..end);
+        offset += end as u64;
+        if read == 0 && buffer.is_empty() {
+            return Ok(offset);
+        }
+    }
+}
+
+fn aggregate_rows(
+    rows: &[u8],
+    offset: u64,
+    cities_stats: &mut FxHashMap<Vec<u8>, Stats>,
+    extras: Extras,
+    options: ParseOptions,
+) -> Result<(), StreamError> {
+    let mut iter = MeasurementIter::with_options(rows, options);
+    while let Some(row) = iter.next() {
+        let (city, measure) = row.map_err(|err| StreamError::Parse {
+            line: iter.line(),
+            err,
+        })?;
+        match cities_stats.get_mut(city) {
+            Some(stats) => stats.update(measure),
+            None => {
+                if std::str::from_utf8(city).is_err() {
+                    let at = city.as_ptr() as usize - rows.as_ptr() as usize;
+                    return Err(StreamError::InvalidUtf8 {
+                        offset: offset + at as u64,
+                    });
+                }
+                cities_stats.insert(city.to_vec(), Stats::new(measure, extras));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        gzip::{aggregate_gzip, aggregate_stream, StreamError, BLOCK_SIZE},
+        process_chunk,
+        table::CityTable,
+        Extras, ParseError, ParseOptions, Stats,
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use pretty_assertions::assert_eq;
+    use rustc_hash::FxHashMap;
+    use std::{collections::BTreeMap, io::Write};
+
+    /// Rows spanning several blocks, so some are cut at a block boundary.
+    fn rows() -> Vec<u8> {
+        let rows: String = (0..3 * BLOCK_SIZE / 10)
+            .map(|i| format!("City{};{}.{}\n", i % 97, (i % 199) as i32 - 99, i % 10))
+            .collect();
+        rows.into_bytes()
+    }
+
+    fn expected(rows: &[u8]) -> BTreeMap<Vec<u8>, Stats> {
+        let mut table = CityTable::default();
+        process_chunk(rows, &mut table, Extras::default(), ParseOptions::default()).unwrap();
+        table
+            .into_entries()
+            .map(|(city, stats)| (city.to_vec(), stats))
+            .collect()
+    }
+
+    fn stream(
+        rows: &[u8],
+        options: ParseOptions,
+    ) -> Result<FxHashMap<Vec<u8>, Stats>, StreamError> {
+        let mut cities_stats = FxHashMap::default();
+        aggregate_stream(rows, &mut cities_stats, Extras::default(), options)?;
+        Ok(cities_stats)
+    }
+
+    fn gunzip(
+        compressed: &[u8],
+        skip_header: bool,
+    ) -> Result<FxHashMap<Vec<u8>, Stats>, StreamError> {
+        let mut cities_stats = FxHashMap::default();
+        let options = ParseOptions::default();
+        aggregate_gzip(
+            compressed,
+            &mut cities_stats,
+            Extras::default(),
+            options,
+            skip_header,
+        )?;
+        Ok(cities_stats)
+    }
+
+    #[test]
+    fn it_aggregates_across_block_boundaries() {
+        let rows = rows();
+        let cities_stats = stream(&rows, ParseOptions::default()).unwrap();
+        assert_eq!(expected(&rows), cities_stats.into_iter().collect());
+    }
+
+    #[test]
+    fn it_aggregates_gzip() {
+        let rows = rows();
+        let compress = |rows: &[u8]| {
+            let mut encoder = GzEncoder::new(vec![], Compression::fast());
+            encoder.write_all(rows).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        let cities_stats = gunzip(&compress(&rows), false).unwrap();
+        assert_eq!(expected(&rows), cities_stats.into_iter().collect());
+        let read = aggregate_gzip(
+            &compress(&rows)[..],
+            &mut FxHashMap::default(),
+            Extras::default(),
+            ParseOptions::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(rows.len() as u64, read);
+
+        let with_header = compress(&[&b"city;temperature\n"[..], &rows].concat());
+        let cities_stats = gunzip(&with_header, true).unwrap();
+        assert_eq!(expected(&rows), cities_stats.into_iter().collect());
+
+        assert!(matches!(gunzip(&rows, false), Err(StreamError::Io(_))));
+    }
+
+    #[test]
+    fn it_skips_a_bom_and_reads_the_last_row_without_newline() {
+        let cities_stats = stream(b"\xef\xbb\xbfA;1.0\nB;2.0", ParseOptions::default()).unwrap();
+        let mut cities: Vec<_> = cities_stats.into_keys().collect();
+        cities.sort_unstable();
+        assert_eq!(vec![b"A".to_vec(), b"B".to_vec()], cities);
+    }
+
+    #[test]
+    fn it_skips_comments() {
+        let options = ParseOptions {
+            allow_comments: true,
+            ..ParseOptions::default()
+        };
+        let cities_stats = stream(b"# city;temperature\nA;1.0\n#B;2.0\n", options).unwrap();
+        assert_eq!(
+            vec![b"A".to_vec()],
+            cities_stats.into_keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_reports_errors_with_stream_offsets() {
+        let malformed = [&rows()[..], b"B 2.0\n"].concat();
+        assert!(matches!(
+            stream(&malformed, ParseOptions::default()),
+            Err(StreamError::Parse { line, .. }) if line == 3 * BLOCK_SIZE as u64 / 10 + 1
+        ));
+        let rows = [&rows()[..], b"K\xf6ln;8.5\n"].concat();
+        let offset = rows.len() as u64 - 9;
+        assert!(matches!(
+            stream(&rows, ParseOptions::default()),
+            Err(StreamError::InvalidUtf8 { offset: o }) if o == offset
+        ));
+        assert!(matches!(
+            stream(b"A;1.0\nB 2.0\n", ParseOptions::default()),
+            Err(StreamError::Parse {
+                line: 2,
+                err: ParseError::MissingSeparator
+            })
+        ));
+    }
+}
@@ -0,0 +1,62 @@
+/// Number of bins, 10°C wide each, from -100°C to 100°C.
+pub const HISTOGRAM_BINS: usize = 20;
+
+/// How many measurements of a city fall in each 10°C bin. Bin `i` holds
+/// [-100 + 10i, -90 + 10i)°C.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Histogram([u32; HISTOGRAM_BINS]);
+
+impl Histogram {
+    #[inline(always)]
+    pub fn push(&mut self, measurement: i32) {
+        // Tenths of a degree above -100°C, in bins of 100 tenths.
+        let bin = (measurement + 1000) / 100;
+        self.0[bin.clamp(0, HISTOGRAM_BINS as i32 - 1) as usize] += 1;
+    }
+
+    /// Adds the counts of a disjoint part of the input.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (ours, theirs) in self.0.iter_mut().zip(other.0) {
+            *ours += theirs;
+        }
+    }
+
+    pub fn bins(&self) -> &[u32; HISTOGRAM_BINS] {
+        &self.0
+    }
+
+    /// The lowest temperature of bin `i`, in whole degrees.
+    pub fn lower_bound(i: usize) -> i32 {
+        10 * i as i32 - 100
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::histogram::{Histogram, HISTOGRAM_BINS};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_counts_measurements_per_bin() {
+        let mut histogram = Histogram::default();
+        for measurement in [-999, -901, -900, -1, 0, 99, 100, 999] {
+            histogram.push(measurement);
+        }
+        let mut expected = [0; HISTOGRAM_BINS];
+        expected[0] = 2;
+        expected[1] = 1;
+        expected[9] = 1;
+        expected[10] = 2;
+        expected[11] = 1;
+        expected[19] = 1;
+        assert_eq!(&expected, histogram.bins());
+
+        let mut merged = histogram.clone();
+        merged.merge(&histogram);
+        assert_eq!(16, merged.bins().iter().sum::<u32>());
+        assert_eq!(
+            (-100, 90),
+            (Histogram::lower_bound(0), Histogram::lower_bound(19))
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use rustc_hash::FxHashMap;
+
+/// Assigns dense ids to the city names of one input, so workers hand over
+/// tables keyed by id and the merge indexes them instead of hashing names.
+/// The names borrow from the input for `'a`, like the workers' tables did.
+#[derive(Default)]
+pub struct Interner<'a> {
+    ids: FxHashMap<&'a [u8], u32>,
+    names: Vec<&'a [u8]>,
+}
+
+impl<'a> Interner<'a> {
+    /// Returns the id of `city`, assigning the next free one if it is new.
+    pub fn intern(&mut self, city: &'a [u8]) -> u32 {
+        if let Some(&id) = self.ids.get(city) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.ids.insert(city, id);
+        self.names.push(city);
+        id
+    }
+
+    /// The interned names, indexed by id.
+    pub fn into_names(self) -> Vec<&'a [u8]> {
+        self.names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interner::Interner;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_assigns_dense_ids() {
+        let mut interner = Interner::default();
+        assert_eq!(0, interner.intern(b"Hamburg"));
+        assert_eq!(1, interner.intern(b"Istanbul"));
+        assert_eq!(0, interner.intern(b"Hamburg"));
+        assert_eq!(vec![&b"Hamburg"[..], b"Istanbul"], interner.into_names());
+    }
+}
@@ -0,0 +1,1461 @@
+pub mod args;
+pub mod check;
+pub mod generate;
+#[cfg(feature = "gzip")]
+pub mod gzip;
+mod histogram;
+#[cfg(not(feature = "rayon"))]
+mod interner;
+#[cfg(feature = "lock-free")]
+pub mod lock_free;
+pub mod merge;
+pub mod output;
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(not(feature = "rayon"))]
+mod pool;
+mod reservoir;
+#[cfg(feature = "serde")]
+mod scaled;
+mod stations;
+pub mod table;
+pub mod timer;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+pub mod validate;
+
+use histogram::Histogram;
+use output::Tenths;
+#[cfg(feature = "rayon")]
+pub use par::multi_thread;
+#[cfg(not(feature = "rayon"))]
+pub use pool::multi_thread;
+use reservoir::Reservoir;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{
+    fmt::{self, Display, Formatter},
+    hash::BuildHasher,
+    io,
+};
+use table::CityTable;
+
+/// Serialized with the scaled fields as the temperatures they stand for;
+/// the median sample and the histogram are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    #[cfg_attr(feature = "serde", serde(with = "scaled::tenths"))]
+    min: i32,
+    #[cfg_attr(feature = "serde", serde(with = "scaled::tenths"))]
+    max: i32,
+    #[cfg_attr(feature = "serde", serde(with = "scaled::tenths"))]
+    sum: i64,
+    /// Sum of the squared scaled measurements, for the standard deviation.
+    #[cfg_attr(feature = "serde", serde(with = "scaled::hundredths"))]
+    sum_sq: i64,
+    count: usize,
+    /// Only sampled with `--median` or `--percentiles`, to keep the hot loop
+    /// lean otherwise.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    reservoir: Option<Box<Reservoir>>,
+    /// Only counted with `--ascii-histogram`, for the same reason.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    histogram: Option<Box<Histogram>>,
+}
+
+/// Per-city data kept on top of min, max, sum and count, only when asked
+/// for since it slows down the hot loop.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Extras {
+    /// Sample the measurements for the median and percentiles.
+    pub sample: bool,
+    /// Count the measurements in 10°C bins.
+    pub histogram: bool,
+}
+
+impl Stats {
+    /// Stats of a city whose first measurement is `measure`, keeping the
+    /// `extras` that are set.
+    #[inline(always)]
+    pub fn new(measure: i32, extras: Extras) -> Stats {
+        let reservoir = extras.sample.then(|| {
+            let mut reservoir = Box::<Reservoir>::default();
+            reservoir.push(measure as i16);
+            reservoir
+        });
+        let histogram = extras.histogram.then(|| {
+            let mut histogram = Box::<Histogram>::default();
+            histogram.push(measure);
+            histogram
+        });
+        Stats {
+            min: measure,
+            max: measure,
+            sum: measure as i64,
+            sum_sq: (measure * measure) as i64,
+            count: 1,
+            reservoir,
+            histogram,
+        }
+    }
+
+    /// Adds `measure` to the stats; the hot loop of the aggregation.
+    #[inline(always)]
+    pub fn update(&mut self, measure: i32) {
+        self.min = measure.min(self.min);
+        self.max = measure.max(self.max);
+        self.count += 1;
+        self.sum += measure as i64;
+        self.sum_sq += (measure * measure) as i64;
+        if let Some(reservoir) = &mut self.reservoir {
+            reservoir.push(measure as i16);
+        }
+        if let Some(histogram) = &mut self.histogram {
+            histogram.push(measure);
+        }
+    }
+
+    /// Stats from the totals of `--format raw`, which has neither the sum of
+    /// squares nor a median sample nor a histogram.
+    pub fn from_raw(sum: i64, count: usize, min: i32, max: i32) -> Stats {
+        Stats {
+            min,
+            max,
+            sum,
+            sum_sq: 0,
+            count,
+            reservoir: None,
+            histogram: None,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Folds the stats of a disjoint part of the input into `self`. Takes
+    /// `other` by value so its reservoir can be merged without copying.
+    #[inline(always)]
+    pub fn merge(&mut self, other: Stats) {
+        self.min = other.min.min(self.min);
+        self.max = other.max.max(self.max);
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        self.count += other.count;
+        if let (Some(ours), Some(theirs)) = (&mut self.reservoir, other.reservoir) {
+            ours.merge(*theirs);
+        }
+        if let (Some(ours), Some(theirs)) = (&mut self.histogram, other.histogram) {
+            ours.merge(&theirs);
+        }
+    }
+}
+
+/// Merges the stats of two disjoint inputs, as if they had been aggregated
+/// together. The smaller map is folded into the larger one.
+pub fn merge_maps<'a>(
+    a: FxHashMap<&'a [u8], Stats>,
+    b: FxHashMap<&'a [u8], Stats>,
+) -> FxHashMap<&'a [u8], Stats> {
+    let (mut into, from) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    for (city, stats) in from {
+        match into.get_mut(city) {
+            Some(into_stats) => into_stats.merge(stats),
+            None => {
+                into.insert(city, stats);
+            }
+        }
+    }
+    into
+}
+
+pub fn process_chunk<'a, S: BuildHasher>(
+    chunk: &'a [u8],
+    cities_stats: &mut CityTable<'a, S>,
+    extras: Extras,
+    options: ParseOptions,
+) -> Result<(), RowError> {
+    let mut rows = MeasurementIter::with_options(chunk, options);
+    while let Some(row) = rows.next() {
+        let (city, measure) = row.map_err(|err| RowError {
+            offset: rows.position(),
+            err,
+        })?;
+        cities_stats.update_or_insert_with(
+            city,
+            |stats| stats.update(measure),
+            || Stats::new(measure, extras),
+        );
+    }
+    Ok(())
+}
+
+/// How rows are read, beyond the `city;[-]d[d].d` layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Skip lines starting with `#`.
+    pub allow_comments: bool,
+    /// Separates the city from the temperature.
+    pub delimiter: u8,
+    /// Warn about and skip rows whose temperature has more integer digits
+    /// than [`parse_temperature`] accepts, instead of failing on them.
+    pub validate_range: bool,
+    /// Line number of the first row, for warnings.
+    pub first_line: u64,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            allow_comments: false,
+            delimiter: b';',
+            validate_range: false,
+            first_line: 1,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// The options for each of `chunks`, which are slices of `buffer` in
+    /// order. Their first lines are only counted when range warnings may
+    /// need them.
+    pub fn for_chunks(self, buffer: &[u8], chunks: &[&[u8]]) -> Vec<ParseOptions> {
+        let mut first_line = self.first_line;
+        let mut counted = 0;
+        chunks
+            .iter()
+            .map(|chunk| {
+                if self.validate_range {
+                    let start = chunk.as_ptr() as usize - buffer.as_ptr() as usize;
+                    first_line += count_newlines(&buffer[counted..start]);
+                    counted = start;
+                }
+                ParseOptions { first_line, ..self }
+            })
+            .collect()
+    }
+}
+
+/// Largest absolute temperature in tenths of a degree, i.e. 99.9.
+pub const MAX_TEMPERATURE: i32 = 999;
+
+/// Whether `temperature` is within ±[`MAX_TEMPERATURE`], warning on stderr
+/// about the row at `line_num` if it is not.
+pub fn validate_measurement(city: &[u8], temperature: i32, line_num: u64) -> bool {
+    if temperature.abs() <= MAX_TEMPERATURE {
+        return true;
+    }
+    eprintln!(
+        "Warning: line {line_num}: city='{}' temperature={} out of range",
+        String::from_utf8_lossy(city),
+        Tenths(temperature)
+    );
+    false
+}
+
+/// Whether `row`, which failed to parse with an invalid temperature, only
+/// failed because the temperature is out of range. Such rows are warned
+/// about by [`validate_measurement`] as the row at `line_num`.
+fn is_out_of_range(row: &[u8], delimiter: u8, line_num: u64) -> bool {
+    let row = row.strip_suffix(b"\r").unwrap_or(row);
+    let Some(end_city) = row.iter().position(|&b| b == delimiter) else {
+        return false;
+    };
+    match parse_wide_temperature(&row[end_city + 1..]) {
+        Some(temperature) => !validate_measurement(&row[..end_city], temperature, line_num),
+        None => false,
+    }
+}
+
+/// The rows of `buffer` as `(city, measure)` pairs, parsed with
+/// [`parse_next_row`]. Ends after the first malformed row, which is then
+/// at [`MeasurementIter::position`].
+pub struct MeasurementIter<'a> {
+    buffer: &'a [u8],
+    pos: usize,
+    line: u64,
+    options: ParseOptions,
+}
+
+impl<'a> MeasurementIter<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        MeasurementIter::with_options(buffer, ParseOptions::default())
+    }
+
+    pub fn with_options(buffer: &'a [u8], options: ParseOptions) -> Self {
+        MeasurementIter {
+            buffer,
+            pos: 0,
+            line: options.first_line,
+            options,
+        }
+    }
+
+    /// Offset of the next row in the buffer, or of the malformed row once
+    /// iteration has stopped at one.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Line number of the row at [`MeasurementIter::position`], counting
+    /// from `options.first_line`.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Ends the iteration at the malformed row at the current position.
+    #[cold]
+    fn fail(&mut self, err: ParseError) -> Option<<Self as Iterator>::Item> {
+        self.buffer = &self.buffer[..self.pos];
+        Some(Err(err))
+    }
+
+    /// Length of the row at the current position if it only failed to parse
+    /// because its temperature is out of range, which has been reported.
+    #[cold]
+    fn out_of_range_row(&self) -> Option<usize> {
+        let slice = &self.buffer[self.pos..];
+        let end_row = slice
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(slice.len());
+        is_out_of_range(&slice[..end_row], self.options.delimiter, self.line)
+            .then_some((end_row + 1).min(slice.len()))
+    }
+
+    /// Moves past any comment lines at the current position.
+    #[cold]
+    fn skip_comments(&mut self) {
+        while self.buffer.get(self.pos) == Some(&b'#') {
+            self.pos = match self.buffer[self.pos..].iter().position(|&b| b == b'\n') {
+                Some(newline) => self.pos + newline + 1,
+                None => self.buffer.len(),
+            };
+            self.line += 1;
+        }
+    }
+}
+
+impl<'a> Iterator for MeasurementIter<'a> {
+    type Item = Result<(&'a [u8], i32), ParseError>;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.options.allow_comments && self.buffer.get(self.pos) == Some(&b'#') {
+                self.skip_comments();
+            }
+            if self.pos >= self.buffer.len() {
+                return None;
+            }
+            match parse_next_row(&self.buffer[self.pos..], self.options.delimiter) {
+                Ok(row) => {
+                    self.pos += row.bytes_consumed;
+                    self.line += 1;
+                    return Some(Ok((row.city, row.temperature)));
+                }
+                Err(ParseError::InvalidTemperature) if self.options.validate_range => {
+                    if let Some(skipped) = self.out_of_range_row() {
+                        self.pos += skipped;
+                        self.line += 1;
+                        continue;
+                    }
+                    return self.fail(ParseError::InvalidTemperature);
+                }
+                Err(err) => return self.fail(err),
+            }
+        }
+    }
+}
+
+#[inline(always)]
+pub fn chunks(buffer: &[u8], chunk_size: usize) -> Vec<&[u8]> {
+    assert!(chunk_size > 0, "chunk_size must be positive");
+    let mut result = vec![];
+    let mut i = 0;
+    while i < buffer.len() {
+        let s = i;
+        i = if i + chunk_size < buffer.len() {
+            i + chunk_size
+        } else {
+            buffer.len()
+        };
+        while i < buffer.len() && buffer[i] != b'\n' {
+            i += 1;
+        }
+        result.push(&buffer[s..i]);
+        i += 1;
+    }
+
+    result
+}
+
+/// Splits `buffer` into at most `n_chunks` chunks with the same number of
+/// rows, give or take one, unlike [`chunks`] which balances bytes. Costs an
+/// extra pass over the buffer to count the rows first.
+pub fn chunks_equal_rows(buffer: &[u8], n_chunks: usize) -> Vec<&[u8]> {
+    assert!(n_chunks > 0, "n_chunks must be positive");
+    let rows = count_rows(buffer) as usize;
+    let mut result = Vec::with_capacity(n_chunks);
+    let mut start = 0;
+    for k in 0..n_chunks {
+        // Rows left over after an even split go to the first chunks.
+        let chunk_rows = rows / n_chunks + usize::from(k < rows % n_chunks);
+        if chunk_rows == 0 || start >= buffer.len() {
+            break;
+        }
+        let end =
+            find_nth_newline(&buffer[start..], chunk_rows).map_or(buffer.len(), |i| start + i);
+        result.push(&buffer[start..end]);
+        start = end + 1;
+    }
+    result
+}
+
+/// Number of distinct cities in `buffer`, see [`unique_cities`].
+pub fn count_unique_cities(buffer: &[u8]) -> Result<usize, RowError> {
+    unique_cities(buffer, ParseOptions::default()).map(|cities| cities.len())
+}
+
+/// The distinct cities in `buffer`. Only looks for the `;` of each row and
+/// skips straight to the next newline, so temperatures are not validated.
+pub fn unique_cities(buffer: &[u8], options: ParseOptions) -> Result<FxHashSet<&[u8]>, RowError> {
+    let mut cities = FxHashSet::default();
+    for row in buffer.split(|&b| b == b'\n') {
+        if row.is_empty() || options.allow_comments && row[0] == b'#' {
+            continue;
+        }
+        let end_city = row
+            .iter()
+            .position(|&b| b == options.delimiter)
+            .ok_or_else(|| RowError {
+                offset: row.as_ptr() as usize - buffer.as_ptr() as usize,
+                err: ParseError::MissingSeparator,
+            })?;
+        cities.insert(&row[..end_city]);
+    }
+    Ok(cities)
+}
+
+/// Byte offset of the first city name in `buffer` that is not valid UTF-8,
+/// if any. Stops at the first malformed row.
+pub fn find_invalid_city(buffer: &[u8], options: ParseOptions) -> Option<usize> {
+    MeasurementIter::with_options(buffer, options)
+        .map_while(Result::ok)
+        .find(|(city, _)| std::str::from_utf8(city).is_err())
+        .map(|(city, _)| city.as_ptr() as usize - buffer.as_ptr() as usize)
+}
+
+/// Number of rows in `buffer`, including a last one without a newline.
+pub fn count_rows(buffer: &[u8]) -> u64 {
+    let unterminated = buffer.last().is_some_and(|&b| b != b'\n');
+    count_newlines(buffer) + u64::from(unterminated)
+}
+
+/// Number of lines in `buffer` starting with `#`.
+pub fn count_comments(buffer: &[u8]) -> u64 {
+    let first = buffer.first() == Some(&b'#');
+    let rest = buffer.windows(2).filter(|pair| pair == b"\n#").count();
+    u64::from(first) + rest as u64
+}
+
+/// Number of `\n` bytes in `buffer`.
+///
+/// Works on 64 byte blocks, each turned into a bitmask of its newlines and
+/// popcounted, like `u8x64::simd_eq(..).to_bitmask()` would with the
+/// nightly-only `std::simd`. The byte compares vectorize on stable, and
+/// multiplying eight 0/1 bytes by [`GATHER_BITS`] moves them into the top
+/// byte as eight mask bits.
+pub fn count_newlines(buffer: &[u8]) -> u64 {
+    let blocks = buffer.chunks_exact(64);
+    let tail = blocks.remainder();
+    let mut count = 0;
+    for block in blocks {
+        let mut is_newline = [0u8; 64];
+        for (flag, &b) in is_newline.iter_mut().zip(block) {
+            *flag = (b == b'\n') as u8;
+        }
+        let mut mask = 0u64;
+        for (i, flags) in is_newline.chunks_exact(8).enumerate() {
+            let flags = u64::from_le_bytes(flags.try_into().unwrap());
+            mask |= (flags.wrapping_mul(GATHER_BITS) >> 56) << (8 * i);
+        }
+        count += mask.count_ones() as u64;
+    }
+    count + tail.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+/// Shifts bit 0 of byte `k` of a word to bit `56 + k` of the product,
+/// without carries between them.
+const GATHER_BITS: u64 = 0x0102_0408_1020_4080;
+
+/// Index of the `n`-th `\n` in `buffer`, counting from 1.
+pub fn find_nth_newline(buffer: &[u8], n: usize) -> Option<usize> {
+    buffer
+        .iter()
+        .enumerate()
+        .filter(|(_, &b)| b == b'\n')
+        .nth(n.checked_sub(1)?)
+        .map(|(i, _)| i)
+}
+
+/// The UTF-8 byte order mark some Windows tools start text files with.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// `buffer` without a leading byte order mark, which would otherwise become
+/// part of the first city name.
+pub fn strip_bom(buffer: &[u8]) -> &[u8] {
+    buffer.strip_prefix(BOM).unwrap_or(buffer)
+}
+
+/// `buffer` after its first line, e.g. a header naming the columns. Empty
+/// if there is only one line.
+pub fn skip_line(buffer: &[u8]) -> &[u8] {
+    match buffer.iter().position(|&b| b == b'\n') {
+        Some(newline) => &buffer[newline + 1..],
+        None => &[],
+    }
+}
+
+/// Shortest well-formed row: a one byte city name followed by `;1.0`.
+const MIN_ROW_LEN: usize = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    RowTooShort,
+    MissingSeparator,
+    InvalidTemperature,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::RowTooShort => write!(f, "row is shorter than {MIN_ROW_LEN} bytes"),
+            ParseError::MissingSeparator => write!(f, "row has no ';' separator"),
+            ParseError::InvalidTemperature => write!(f, "temperature is not in the form [-]d[d].d"),
+        }
+    }
+}
+
+/// A malformed row, `offset` bytes into the buffer being aggregated.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RowError {
+    pub offset: usize,
+    pub err: ParseError,
+}
+
+impl RowError {
+    /// The same error with its offset into `buffer` instead of into
+    /// `chunk`, one of its slices.
+    pub fn within(self, buffer: &[u8], chunk: &[u8]) -> RowError {
+        RowError {
+            offset: chunk.as_ptr() as usize - buffer.as_ptr() as usize + self.offset,
+            ..self
+        }
+    }
+
+    /// Line number of the malformed row, counting the first line of
+    /// `buffer` as `first_line`.
+    pub fn line(&self, buffer: &[u8], first_line: u64) -> u64 {
+        first_line + count_newlines(&buffer[..self.offset])
+    }
+}
+
+/// Why [`multi_thread`] stopped without results.
+#[derive(Debug)]
+pub enum AggregateError {
+    Row(RowError),
+    /// The worker threads could not be started.
+    Spawn(io::Error),
+}
+
+impl From<RowError> for AggregateError {
+    fn from(err: RowError) -> Self {
+        AggregateError::Row(err)
+    }
+}
+
+/// A parsed row.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Measurement<'a> {
+    pub city: &'a [u8],
+    /// In tenths of a degree.
+    pub temperature: i32,
+    /// Length of the row including its newline, i.e. the offset of the next
+    /// row. The last row of a buffer may have no newline, in which case this
+    /// is the rest of the buffer.
+    pub bytes_consumed: usize,
+}
+
+/// Stats of a single measurement, without any extras.
+impl From<Measurement<'_>> for Stats {
+    fn from(measurement: Measurement<'_>) -> Stats {
+        Stats::new(measurement.temperature, Extras::default())
+    }
+}
+
+#[inline(always)]
+pub fn parse_next_row(slice: &[u8], delimiter: u8) -> Result<Measurement<'_>, ParseError> {
+    let end_row = slice
+        .iter()
+        .position(|&b| b == b'\n')
+        .unwrap_or(slice.len());
+    // Rows of files written on Windows end with `\r\n`.
+    let row = slice[..end_row]
+        .strip_suffix(b"\r")
+        .unwrap_or(&slice[..end_row]);
+    if row.len() < MIN_ROW_LEN {
+        return Err(ParseError::RowTooShort);
+    }
+    let end_city = row
+        .iter()
+        .position(|&b| b == delimiter)
+        .ok_or(ParseError::MissingSeparator)?;
+    let measure = parse_temperature(&row[end_city + 1..])?;
+
+    Ok(Measurement {
+        city: &row[0..end_city],
+        temperature: measure,
+        bytes_consumed: (end_row + 1).min(slice.len()),
+    })
+}
+
+/// Parses a `[-]d[d].d` temperature field, the bytes after the `;`, into
+/// tenths of a degree.
+#[inline(always)]
+pub fn parse_temperature(field: &[u8]) -> Result<i32, ParseError> {
+    let digit = |i: usize| match field.get(i) {
+        Some(b @ b'0'..=b'9') => Ok((b - b'0') as i32),
+        _ => Err(ParseError::InvalidTemperature),
+    };
+
+    let mut i = 0;
+    let sign: i32 = if field.first() == Some(&b'-') {
+        i += 1;
+        -1
+    } else {
+        1
+    };
+    let mut measure = digit(i)?;
+    i += 1;
+    if field.get(i) != Some(&b'.') {
+        measure = measure * 10 + digit(i)?;
+        i += 1;
+    }
+    if field.get(i) != Some(&b'.') {
+        return Err(ParseError::InvalidTemperature);
+    }
+    i += 1;
+    measure = 10 * measure + digit(i)?;
+    i += 1;
+    if i != field.len() {
+        return Err(ParseError::InvalidTemperature);
+    }
+
+    Ok(sign * measure)
+}
+
+/// Parses a `[-]d+.d` temperature field, with up to eight integer digits so
+/// the tenths fit an `i32`, into tenths of a degree.
+fn parse_wide_temperature(field: &[u8]) -> Option<i32> {
+    let (negative, digits) = match field.split_first() {
+        Some((b'-', digits)) => (true, digits),
+        _ => (false, field),
+    };
+    let (integer, fraction) = digits.split_at(digits.len().checked_sub(2)?);
+    if integer.is_empty()
+        || integer.len() > 8
+        || fraction[0] != b'.'
+        || !integer.iter().chain(&fraction[1..]).all(u8::is_ascii_digit)
+    {
+        return None;
+    }
+    let tenths = integer
+        .iter()
+        .chain(&fraction[1..])
+        .fold(0, |acc, &b| acc * 10 + (b - b'0') as i32);
+    Some(if negative { -tenths } else { tenths })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        args::Unit,
+        chunks, chunks_equal_rows, count_comments, count_newlines, count_rows, count_unique_cities,
+        find_invalid_city, find_nth_newline, merge_maps, multi_thread,
+        output::{write_stats, Column},
+        parse_next_row, parse_temperature, parse_wide_temperature, process_chunk, skip_line,
+        strip_bom,
+        table::CityTable,
+        timer::PhaseTimer,
+        unique_cities, validate_measurement, AggregateError, Extras, Measurement, MeasurementIter,
+        ParseError, ParseOptions, RowError, Stats,
+    };
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+    use rustc_hash::{FxHashMap, FxHasher};
+    use std::{collections::BTreeMap, hash::BuildHasherDefault};
+
+    fn content() -> &'static [u8] {
+        r#"Hamburg;12.0
+Bulawayo;8.9
+Palembang;38.8
+St. John's;15.2
+Cracow;12.6
+Bridgetown;26.9
+Istanbul;6.2
+Roseau;34.4
+Conakry;31.2
+Istanbul;23.0"#
+            .as_bytes()
+    }
+
+    #[test]
+    fn it_chunks_content() {
+        let content = content();
+        assert_eq!(
+            vec![
+                r#"Hamburg;12.0
+Bulawayo;8.9
+Palembang;38.8
+St. John's;15.2"#
+                    .as_bytes(),
+                r#"Cracow;12.6
+Bridgetown;26.9
+Istanbul;6.2
+Roseau;34.4"#
+                    .as_bytes(),
+                r#"Conakry;31.2
+Istanbul;23.0"#
+                    .as_bytes()
+            ],
+            chunks(content, content.len() / 3)
+        );
+    }
+
+    #[test]
+    fn it_parses_row() {
+        let content = content();
+
+        let result: Vec<(&[u8], i32)> = MeasurementIter::new(content).map(Result::unwrap).collect();
+
+        assert_eq!(
+            vec![
+                ("Hamburg".as_bytes(), 120),
+                ("Bulawayo".as_bytes(), 89),
+                ("Palembang".as_bytes(), 388),
+                ("St. John's".as_bytes(), 152),
+                ("Cracow".as_bytes(), 126),
+                ("Bridgetown".as_bytes(), 269),
+                ("Istanbul".as_bytes(), 62),
+                ("Roseau".as_bytes(), 344),
+                ("Conakry".as_bytes(), 312),
+                ("Istanbul".as_bytes(), 230),
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn it_parses_temperatures_with_leading_nine() {
+        let cases: [(&[u8], i32); 6] = [
+            (b"Hamburg;9.9\n", 99),
+            (b"Hamburg;90.0\n", 900),
+            (b"Hamburg;99.9\n", 999),
+            (b"Roseau;-9.9\n", -99),
+            (b"Roseau;-90.0\n", -900),
+            (b"Roseau;-99.9\n", -999),
+        ];
+        for (row, expected) in cases {
+            let measurement = parse_next_row(row, b';').unwrap();
+            assert_eq!(expected, measurement.temperature);
+            assert_eq!(row.len(), measurement.bytes_consumed);
+        }
+    }
+
+    #[test]
+    fn it_sums_beyond_i32_range() {
+        let rows = 2_200_000;
+        let content = "Hamburg;99.9\n".repeat(rows);
+        let mut table = CityTable::default();
+        process_chunk(
+            content.as_bytes(),
+            &mut table,
+            Extras::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        let (_, stats) = table.into_entries().next().unwrap();
+
+        assert_eq!(rows, stats.count);
+        assert_eq!(999 * rows as i64, stats.sum);
+        assert_eq!(999 * 999 * rows as i64, stats.sum_sq);
+        assert!(stats.sum > i32::MAX as i64);
+    }
+
+    #[test]
+    fn it_parses_rows_longer_than_255_bytes() {
+        for len in [64, 128, 200, 256, 300] {
+            let city = "a".repeat(len - ";12.3\n".len());
+            let row = format!("{city};12.3\nHamburg;1.0\n");
+            assert_eq!(
+                Ok(Measurement {
+                    city: city.as_bytes(),
+                    temperature: 123,
+                    bytes_consumed: len
+                }),
+                parse_next_row(row.as_bytes(), b';')
+            );
+        }
+    }
+
+    #[test]
+    fn it_skips_comments() {
+        let options = ParseOptions {
+            allow_comments: true,
+            ..ParseOptions::default()
+        };
+        let content = b"# header\n#\na;1.0\n# a;9.9\n## b\nb;2.0\n# trailing";
+        let rows: Vec<_> = MeasurementIter::with_options(content, options).collect();
+        assert_eq!(vec![Ok((&b"a"[..], 10)), Ok((&b"b"[..], 20))], rows);
+        assert_eq!(
+            Some(Err(ParseError::MissingSeparator)),
+            MeasurementIter::new(content).next()
+        );
+
+        assert_eq!(5, count_comments(content));
+        assert_eq!(2, count_rows(content) - count_comments(content));
+        assert_eq!(0, count_comments(b"a;1.0\n"));
+        let cities = unique_cities(content, options).unwrap();
+        assert_eq!(2, cities.len());
+    }
+
+    #[test]
+    fn it_counts_the_lines_it_moves_past() {
+        let options = ParseOptions {
+            allow_comments: true,
+            validate_range: true,
+            first_line: 3,
+            ..ParseOptions::default()
+        };
+        let mut rows =
+            MeasurementIter::with_options(b"# a\n# b\na;1.0\nb;100.0\nc;2.0\nd;\n", options);
+        assert_eq!(3, rows.line());
+        assert_eq!(2, rows.by_ref().filter(Result::is_ok).count());
+        assert_eq!(8, rows.line());
+        assert_eq!(
+            b"d;\n",
+            &b"# a\n# b\na;1.0\nb;100.0\nc;2.0\nd;\n"[rows.position()..]
+        );
+    }
+
+    #[test]
+    fn it_skips_out_of_range_rows() {
+        let options = ParseOptions {
+            validate_range: true,
+            ..ParseOptions::default()
+        };
+        let content = b"a;1.0\nHamburg;100.0\nb;-1234.5\r\nc;2.0";
+        let rows: Vec<_> = MeasurementIter::with_options(content, options).collect();
+        assert_eq!(vec![Ok((&b"a"[..], 10)), Ok((&b"c"[..], 20))], rows);
+        assert_eq!(
+            vec![Ok((&b"a"[..], 10)), Err(ParseError::InvalidTemperature)],
+            MeasurementIter::new(content).collect::<Vec<_>>()
+        );
+        // Still malformed rather than out of range.
+        let rows: Vec<_> = MeasurementIter::with_options(b"a;100\nb;1.0\n", options).collect();
+        assert_eq!(vec![Err(ParseError::InvalidTemperature)], rows);
+
+        assert!(validate_measurement(b"a", 999, 1));
+        assert!(validate_measurement(b"a", -999, 1));
+        assert!(!validate_measurement(b"Hamburg", 1000, 2));
+        assert!(!validate_measurement(b"a", -1000, 3));
+
+        assert_eq!(Some(1000), parse_wide_temperature(b"100.0"));
+        assert_eq!(Some(-12345), parse_wide_temperature(b"-1234.5"));
+        for field in [&b"100"[..], b".5", b"-.5", b"1.", b"1x0.0", b"123456789.0"] {
+            assert_eq!(None, parse_wide_temperature(field));
+        }
+    }
+
+    #[test]
+    fn it_numbers_the_first_line_of_each_chunk() {
+        let content = b"a;1.0\nb;2.0\nc;3.0\nd;4.0\n";
+        let chunks = chunks(content, 8);
+        let first_lines = |options: ParseOptions| -> Vec<u64> {
+            options
+                .for_chunks(content, &chunks)
+                .iter()
+                .map(|options| options.first_line)
+                .collect()
+        };
+        let options = ParseOptions {
+            validate_range: true,
+            first_line: 2,
+            ..ParseOptions::default()
+        };
+        assert_eq!(vec![2, 4], first_lines(options));
+        assert_eq!(vec![1, 1], first_lines(ParseOptions::default()));
+    }
+
+    #[test]
+    fn it_stops_iterating_after_a_malformed_row() {
+        let rows: Vec<_> = MeasurementIter::new(b"a;1.0\nb;1x.0\nc;3.0\n").collect();
+        assert_eq!(
+            vec![
+                Ok(("a".as_bytes(), 10)),
+                Err(ParseError::InvalidTemperature)
+            ],
+            rows
+        );
+        assert_eq!(None, MeasurementIter::new(b"").next());
+
+        let mut rows = MeasurementIter::new(b"a;1.0\nb;1x.0\nc;3.0\n");
+        assert_eq!(1, rows.by_ref().filter(Result::is_ok).count());
+        assert_eq!(6, rows.position());
+    }
+
+    #[test]
+    fn it_reports_the_line_of_a_malformed_row() {
+        let content = [content(), b"\nHamburg 12.0\nBulawayo;8.9"].concat();
+        for chunk_size in [16, 128, 1 << 20] {
+            let Err(AggregateError::Row(err)) = multi_thread(
+                &content,
+                4,
+                chunk_size,
+                Extras::default(),
+                ParseOptions::default(),
+                BuildHasherDefault::<FxHasher>::default(),
+                &mut PhaseTimer::new(),
+            ) else {
+                panic!("no row error with chunk size {chunk_size}");
+            };
+            assert_eq!(content.len() - 25, err.offset, "{chunk_size}");
+            assert_eq!(ParseError::MissingSeparator, err.err);
+            assert_eq!(11, err.line(&content, 1));
+            assert_eq!(12, err.line(&content, 2));
+        }
+    }
+
+    #[test]
+    fn it_reports_the_first_of_many_malformed_rows() {
+        let content = [content(), b"\n", &b"Hamburg 12.0\n".repeat(100)].concat();
+        for _ in 0..20 {
+            let Err(AggregateError::Row(err)) = multi_thread(
+                &content,
+                4,
+                16,
+                Extras::default(),
+                ParseOptions::default(),
+                BuildHasherDefault::<FxHasher>::default(),
+                &mut PhaseTimer::new(),
+            ) else {
+                panic!("no row error");
+            };
+            assert_eq!(11, err.line(&content, 1));
+        }
+    }
+
+    #[test]
+    fn it_chunks_equal_rows() {
+        // Rows of very different lengths, which byte-sized chunks split unevenly.
+        let content = "a;1.0\nPalembang Palembang Palembang;38.8\nb;2.0\nc;3.0\nd;4.0";
+        let result = chunks_equal_rows(content.as_bytes(), 2);
+        assert_eq!(
+            vec![
+                "a;1.0\nPalembang Palembang Palembang;38.8\nb;2.0".as_bytes(),
+                "c;3.0\nd;4.0".as_bytes(),
+            ],
+            result
+        );
+        assert_eq!(5, chunks_equal_rows(content.as_bytes(), 8).len());
+        assert_eq!(
+            chunks_equal_rows(content.as_bytes(), 3).join(&b'\n'),
+            content.as_bytes()
+        );
+        assert_eq!(Vec::<&[u8]>::new(), chunks_equal_rows(b"", 3));
+    }
+
+    #[test]
+    fn it_counts_newlines_across_blocks() {
+        let content = "Hamburg;12.0\nBulawayo;8.9\n".repeat(20);
+        for len in [0, 1, 63, 64, 65, 127, 128, 129, content.len()] {
+            let buffer = &content.as_bytes()[..len];
+            let expected = buffer.iter().filter(|&&b| b == b'\n').count() as u64;
+            assert_eq!(expected, count_newlines(buffer), "{len}");
+        }
+        assert_eq!(64, count_newlines(&[b'\n'; 64]));
+    }
+
+    #[test]
+    fn it_counts_unique_cities() {
+        assert_eq!(Ok(9), count_unique_cities(content()));
+        assert_eq!(Ok(0), count_unique_cities(b""));
+        assert_eq!(
+            Err(RowError {
+                offset: 12,
+                err: ParseError::MissingSeparator
+            }),
+            count_unique_cities(b"Hamburg;1.0\nHamburg 1.0\n")
+        );
+    }
+
+    #[test]
+    fn it_finds_invalid_city_names() {
+        assert_eq!(None, find_invalid_city(content(), ParseOptions::default()));
+        assert_eq!(
+            Some(13),
+            find_invalid_city(b"Hamburg;12.0\nK\xf6ln;8.5\n", ParseOptions::default())
+        );
+        assert_eq!(
+            None,
+            find_invalid_city(b"Hamburg 12.0\nK\xf6ln;8.5\n", ParseOptions::default())
+        );
+    }
+
+    #[test]
+    fn it_counts_rows() {
+        let content = "Hamburg;12.0\n".repeat(1000);
+        assert_eq!(1000, count_rows(content.as_bytes()));
+        assert_eq!(1000, count_rows(content.trim_end().as_bytes()));
+        assert_eq!(0, count_rows(b""));
+    }
+
+    #[test]
+    fn it_finds_the_nth_newline() {
+        let content = b"a\nbb\nccc\n";
+        assert_eq!(3, count_newlines(content));
+        assert_eq!(Some(1), find_nth_newline(content, 1));
+        assert_eq!(Some(8), find_nth_newline(content, 3));
+        assert_eq!(None, find_nth_newline(content, 4));
+        assert_eq!(None, find_nth_newline(content, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk_size must be positive")]
+    fn it_rejects_zero_chunks() {
+        chunks(content(), 0);
+    }
+
+    #[test]
+    fn it_parses_a_last_row_without_newline() {
+        assert_eq!(
+            Ok(Measurement {
+                city: b"Hamburg",
+                temperature: 120,
+                bytes_consumed: 12
+            }),
+            parse_next_row(b"Hamburg;12.0", b';')
+        );
+        // The corpus has no trailing newline.
+        let rows: Result<Vec<_>, _> = MeasurementIter::new(content()).collect();
+        assert_eq!(10, rows.unwrap().len());
+
+        let mut table = CityTable::default();
+        process_chunk(
+            content(),
+            &mut table,
+            Extras::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        let terminated = [content(), b"\n"].concat();
+        let mut expected = CityTable::default();
+        process_chunk(
+            &terminated,
+            &mut expected,
+            Extras::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            expected.into_entries().collect::<BTreeMap<_, _>>(),
+            table.into_entries().collect::<BTreeMap<_, _>>()
+        );
+    }
+
+    #[test]
+    fn it_parses_other_delimiters() {
+        for delimiter in [b'\t', b'|'] {
+            let row = [&b"St. John's"[..], &[delimiter], b"15.2\n"].concat();
+            assert_eq!(
+                Ok(Measurement {
+                    city: b"St. John's",
+                    temperature: 152,
+                    bytes_consumed: row.len()
+                }),
+                parse_next_row(&row, delimiter)
+            );
+            assert_eq!(
+                Err(ParseError::MissingSeparator),
+                parse_next_row(&row, b';')
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_short_rows() {
+        assert_eq!(Err(ParseError::RowTooShort), parse_next_row(b"a\n", b';'));
+        assert_eq!(
+            Err(ParseError::RowTooShort),
+            parse_next_row(b";1.0\n", b';')
+        );
+        assert_eq!(
+            Ok(Measurement {
+                city: b"a",
+                temperature: 10,
+                bytes_consumed: 6
+            }),
+            parse_next_row(b"a;1.0\n", b';')
+        );
+    }
+
+    #[test]
+    fn it_parses_temperature_fields() {
+        for (field, expected) in [
+            ("0.0", Ok(0)),
+            ("12.0", Ok(120)),
+            ("-34.4", Ok(-344)),
+            ("-0.1", Ok(-1)),
+            ("99.9", Ok(999)),
+            ("", Err(ParseError::InvalidTemperature)),
+            ("-", Err(ParseError::InvalidTemperature)),
+            ("1.", Err(ParseError::InvalidTemperature)),
+            (".5", Err(ParseError::InvalidTemperature)),
+            ("100.0", Err(ParseError::InvalidTemperature)),
+            ("1.23", Err(ParseError::InvalidTemperature)),
+            ("+1.0", Err(ParseError::InvalidTemperature)),
+        ] {
+            assert_eq!(expected, parse_temperature(field.as_bytes()), "{field}");
+        }
+    }
+
+    #[test]
+    fn it_parses_crlf_rows() {
+        let content = "Hamburg;12.0\r\nRoseau;-3.4\r\nBulawayo;8.9".as_bytes();
+        let rows: Vec<_> = MeasurementIter::new(content).collect();
+        assert_eq!(
+            vec![
+                Ok(("Hamburg".as_bytes(), 120)),
+                Ok(("Roseau".as_bytes(), -34)),
+                Ok(("Bulawayo".as_bytes(), 89)),
+            ],
+            rows
+        );
+        assert_eq!(
+            Ok(Measurement {
+                city: b"a",
+                temperature: 10,
+                bytes_consumed: 7
+            }),
+            parse_next_row(b"a;1.0\r\n", b';')
+        );
+        assert_eq!(
+            Err(ParseError::RowTooShort),
+            parse_next_row(b"a;1\r\n", b';')
+        );
+    }
+
+    #[test]
+    fn it_strips_a_leading_bom() {
+        assert_eq!(b"Hamburg;12.0\n", strip_bom(b"\xEF\xBB\xBFHamburg;12.0\n"));
+        assert_eq!(b"Hamburg;12.0\n", strip_bom(b"Hamburg;12.0\n"));
+        assert_eq!(b"\xEF\xBB", strip_bom(b"\xEF\xBB"));
+    }
+
+    #[test]
+    fn it_skips_a_line() {
+        assert_eq!(
+            b"Hamburg;12.0\n",
+            skip_line(b"city;temperature\nHamburg;12.0\n")
+        );
+        assert_eq!(b"", skip_line(b"city;temperature\n"));
+        assert_eq!(b"", skip_line(b"city;temperature"));
+        assert_eq!(b"", skip_line(b""));
+    }
+
+    #[test]
+    fn it_rejects_malformed_rows() {
+        assert_eq!(
+            Err(ParseError::MissingSeparator),
+            parse_next_row(b"Hamburg 12.0\nBulawayo;8.9\n", b';')
+        );
+        for row in [
+            "Hamburg;1x.0\n",
+            "Hamburg;12.\n",
+            "Hamburg;123.0\n",
+            "Hamburg;12.05\n",
+            "Hamburg;-\n",
+            "Hamb;\n",
+        ] {
+            assert_eq!(
+                Err(ParseError::InvalidTemperature),
+                parse_next_row(row.as_bytes(), b';')
+            );
+        }
+    }
+
+    #[test]
+    fn it_chunks_without_trailing_empty_slice() {
+        // 4 rows of 6 bytes each: every row starts at a multiple of the chunk size.
+        let content = "a;1.0\nb;2.0\nc;3.0\nd;4.0\n".as_bytes();
+        for chunk_size in [6, 12, 24] {
+            let result = chunks(content, chunk_size);
+            assert!(result.iter().all(|chunk| !chunk.is_empty()));
+            let joined = result.join(&b'\n');
+            assert_eq!(content.trim_ascii_end(), joined.trim_ascii_end());
+        }
+        assert_eq!(Vec::<&[u8]>::new(), chunks(b"", 3));
+        let mut timer = PhaseTimer::new();
+        let hasher = BuildHasherDefault::<FxHasher>::default();
+        let cities_stats = multi_thread(
+            b"",
+            4,
+            128,
+            Extras::default(),
+            ParseOptions::default(),
+            hasher,
+            &mut timer,
+        );
+        assert_eq!(Vec::<(&[u8], Stats)>::new(), cities_stats.unwrap());
+    }
+
+    #[test]
+    fn it_finds_newlines_past_the_first_64_bytes() {
+        for newline_pos in [64, 65, 96, 127] {
+            let city = "a".repeat(newline_pos - ";12.3".len());
+            let row = format!("{city};12.3\n{}", "b".repeat(64));
+            let measurement = parse_next_row(row.as_bytes(), b';').unwrap();
+            assert_eq!(newline_pos + 1, measurement.bytes_consumed);
+        }
+    }
+
+    /// Straightforward parse of a `city;[-]d[d].d` row without its newline,
+    /// to check `parse_next_row` against.
+    fn reference_parse(row: &str) -> (&str, i32) {
+        let (city, temperature) = row.rsplit_once(';').unwrap();
+        let temperature: f64 = temperature.parse().unwrap();
+        (city, (temperature * 10.0).round() as i32)
+    }
+
+    /// Digit by digit parse of a well-formed temperature field, as an oracle
+    /// for [`parse_temperature`].
+    fn parse_temperature_scalar(field: &[u8]) -> i32 {
+        let (sign, digits) = match field.split_first() {
+            Some((b'-', digits)) => (-1, digits),
+            _ => (1, field),
+        };
+        let mut measure = 0;
+        for &b in digits {
+            if b != b'.' {
+                measure = measure * 10 + (b - b'0') as i32;
+            }
+        }
+        sign * measure
+    }
+
+    proptest! {
+        #[test]
+        fn it_parses_temperatures_like_the_scalar_oracle(field in "-?[0-9]?[0-9]\\.[0-9]") {
+            prop_assert_eq!(
+                Ok(parse_temperature_scalar(field.as_bytes())),
+                parse_temperature(field.as_bytes())
+            );
+        }
+
+        #[test]
+        fn it_only_accepts_fields_the_oracle_agrees_with(field in "[-.0-9]{0,6}") {
+            if let Ok(measure) = parse_temperature(field.as_bytes()) {
+                prop_assert_eq!(parse_temperature_scalar(field.as_bytes()), measure);
+            }
+        }
+
+        #[test]
+        fn it_parses_like_the_reference(
+            city in "[a-zA-Z .'\\-]{1,100}",
+            measure in -999..=999i32,
+            rest in "[a-z]{0,10}",
+        ) {
+            let sign = if measure < 0 { "-" } else { "" };
+            let row = format!("{city};{sign}{}.{}", measure.abs() / 10, measure.abs() % 10);
+            let buffer = format!("{row}\n{rest}");
+            let (expected_city, expected_measure) = reference_parse(&row);
+            prop_assert_eq!(
+                Ok(Measurement {
+                    city: expected_city.as_bytes(),
+                    temperature: expected_measure,
+                    bytes_consumed: row.len() + 1,
+                }),
+                parse_next_row(buffer.as_bytes(), b';')
+            );
+        }
+    }
+
+    #[test]
+    fn it_updates_stats() {
+        let mut stats = Stats::new(120, Extras::default());
+        for measure in [-50, 999, 0] {
+            stats.update(measure);
+        }
+        assert_eq!(
+            Stats {
+                min: -50,
+                max: 999,
+                sum: 1069,
+                sum_sq: 120 * 120 + 50 * 50 + 999 * 999,
+                count: 4,
+                reservoir: None,
+                histogram: None,
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn it_merges_stats() {
+        let mut whole = CityTable::default();
+        process_chunk(
+            content(),
+            &mut whole,
+            Extras::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+
+        let mut merged: BTreeMap<_, Stats> = BTreeMap::new();
+        for chunk in chunks(content(), content().len() / 3) {
+            let mut table = CityTable::default();
+            process_chunk(
+                chunk,
+                &mut table,
+                Extras::default(),
+                ParseOptions::default(),
+            )
+            .unwrap();
+            for (city, stats) in table.into_entries() {
+                match merged.get_mut(city) {
+                    Some(merged_stats) => merged_stats.merge(stats),
+                    None => {
+                        merged.insert(city, stats);
+                    }
+                }
+            }
+        }
+        let whole: BTreeMap<_, _> = whole.into_entries().collect();
+        assert_eq!(whole, merged);
+    }
+
+    #[test]
+    fn it_counts_every_measurement_in_the_histogram() {
+        let content: String = (0..5_000)
+            .map(|i| format!("City{};{:.1}\n", i % 7, (i * 37 % 1999 - 999) as f64 / 10.0))
+            .collect();
+        let extras = Extras {
+            histogram: true,
+            ..Extras::default()
+        };
+        // Small chunks, so the histograms of several workers get merged.
+        let cities_stats = multi_thread(
+            content.as_bytes(),
+            4,
+            1024,
+            extras,
+            ParseOptions::default(),
+            BuildHasherDefault::<FxHasher>::default(),
+            &mut PhaseTimer::new(),
+        )
+        .unwrap();
+        assert_eq!(7, cities_stats.len());
+        for (city, stats) in &cities_stats {
+            let histogram = stats.histogram.as_ref().unwrap();
+            let total: u32 = histogram.bins().iter().sum();
+            assert_eq!(stats.count, total as usize, "{city:?}");
+        }
+    }
+
+    #[test]
+    fn it_merges_maps() {
+        let (first, second) = content().split_at(find_nth_newline(content(), 4).unwrap() + 1);
+        let map = |chunk| {
+            let mut table = CityTable::default();
+            process_chunk(
+                chunk,
+                &mut table,
+                Extras::default(),
+                ParseOptions::default(),
+            )
+            .unwrap();
+            table.into_entries().collect::<FxHashMap<_, _>>()
+        };
+        let mut whole = CityTable::default();
+        process_chunk(
+            content(),
+            &mut whole,
+            Extras::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+
+        let merged: BTreeMap<_, _> = merge_maps(map(first), map(second)).into_iter().collect();
+        assert_eq!(whole.into_entries().collect::<BTreeMap<_, _>>(), merged);
+    }
+
+    #[test]
+    fn it_writes_counts() {
+        let mut table = CityTable::default();
+        process_chunk(
+            content(),
+            &mut table,
+            Extras::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        let cities_stats: BTreeMap<_, _> = table.into_entries().collect();
+        assert_eq!(9, cities_stats.len());
+        for (city, stats) in &cities_stats {
+            let mut out = vec![];
+            write_stats(
+                &mut out,
+                city,
+                stats,
+                &[Column::Min, Column::Avg, Column::Max, Column::Count],
+                Unit::Celsius,
+            )
+            .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            let expected = if *city == b"Istanbul" { "/2" } else { "/1" };
+            assert!(out.ends_with(expected), "{out}");
+        }
+        let mut out = vec![];
+        let istanbul = &cities_stats[b"Istanbul".as_slice()];
+        write_stats(
+            &mut out,
+            b"Istanbul",
+            istanbul,
+            &[Column::Min, Column::Avg, Column::Max, Column::Count],
+            Unit::Celsius,
+        )
+        .unwrap();
+        assert_eq!("Istanbul=6.2/14.60/23.0/2", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn it_borrows_the_city_names_from_the_buffer() {
+        let content = "Town A;1.0\nTown B;2.0\n".repeat(100);
+        let cities_stats = multi_thread(
+            content.as_bytes(),
+            4,
+            128,
+            Extras::default(),
+            ParseOptions::default(),
+            BuildHasherDefault::<FxHasher>::default(),
+            &mut PhaseTimer::new(),
+        )
+        .unwrap();
+        assert_eq!(2, cities_stats.len());
+        let range = content.as_bytes().as_ptr_range();
+        for (city, _) in &cities_stats {
+            assert!(range.contains(&city.as_ptr()), "{city:?}");
+        }
+    }
+}
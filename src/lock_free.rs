@@ -0,0 +1,183 @@
+use crate::{chunks, timer::PhaseTimer, MeasurementIter, ParseOptions, RowError, Stats};
+use dashmap::DashMap;
+use rustc_hash::FxHasher;
+use std::{
+    hash::BuildHasherDefault,
+    panic,
+    sync::atomic::{AtomicI16, AtomicI64, AtomicUsize, Ordering},
+    thread,
+};
+
+/// Stats that any number of threads may update at once. Scaled
+/// temperatures are within ±999, so the extremes fit in an `i16`.
+struct AtomicStatsCell {
+    min: AtomicI16,
+    max: AtomicI16,
+    sum: AtomicI64,
+    sum_sq: AtomicI64,
+    count: AtomicUsize,
+}
+
+impl Default for AtomicStatsCell {
+    fn default() -> Self {
+        AtomicStatsCell {
+            min: AtomicI16::new(i16::MAX),
+            max: AtomicI16::new(i16::MIN),
+            sum: AtomicI64::new(0),
+            sum_sq: AtomicI64::new(0),
+            count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl AtomicStatsCell {
+    #[inline(always)]
+    fn update(&self, measure: i32) {
+        self.min.fetch_min(measure as i16, Ordering::Relaxed);
+        self.max.fetch_max(measure as i16, Ordering::Relaxed);
+        self.sum.fetch_add(measure as i64, Ordering::Relaxed);
+        self.sum_sq
+            .fetch_add((measure * measure) as i64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn into_stats(self) -> Stats {
+        Stats {
+            min: self.min.into_inner() as i32,
+            max: self.max.into_inner() as i32,
+            sum: self.sum.into_inner(),
+            sum_sq: self.sum_sq.into_inner(),
+            count: self.count.into_inner(),
+            reservoir: None,
+            histogram: None,
+        }
+    }
+}
+
+/// Aggregates `buffer` on `num_threads` scoped workers that all update one
+/// shared map, trading the per-thread tables and the merge for contention
+/// on the map's shards and on the cells of popular cities.
+pub fn multi_thread<'a>(
+    buffer: &'a [u8],
+    num_threads: usize,
+    chunk_size: usize,
+    options: ParseOptions,
+    timer: &mut PhaseTimer,
+) -> Result<Vec<(&'a [u8], Stats)>, RowError> {
+    let chunks = chunks(buffer, chunk_size);
+    let chunks: Vec<_> = chunks
+        .iter()
+        .copied()
+        .zip(options.for_chunks(buffer, &chunks))
+        .collect();
+    timer.phase("chunks");
+    let next_chunk = AtomicUsize::new(0);
+    let cities_stats: DashMap<&[u8], AtomicStatsCell, BuildHasherDefault<FxHasher>> =
+        DashMap::default();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads.min(chunks.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    while let Some(&(chunk, options)) =
+                        chunks.get(next_chunk.fetch_add(1, Ordering::Relaxed))
+                    {
+                        let mut rows = MeasurementIter::with_options(chunk, options);
+                        while let Some(row) = rows.next() {
+                            let (city, measure) = row.map_err(|err| {
+                                RowError {
+                                    offset: rows.position(),
+                                    err,
+                                }
+                                .within(buffer, chunk)
+                            })?;
+                            match cities_stats.get(city) {
+                                Some(cell) => cell.update(measure),
+                                None => cities_stats.entry(city).or_default().update(measure),
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        // Chunks are claimed in order, so whichever worker met the first
+        // malformed row has reported it; the others may be further on.
+        let mut errors: Vec<RowError> = vec![];
+        for handle in handles {
+            match handle.join() {
+                Ok(result) => errors.extend(result.err()),
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+        match errors.into_iter().min_by_key(|err| err.offset) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    })?;
+    timer.phase("workers");
+
+    let cities_stats = cities_stats
+        .into_iter()
+        .map(|(city, cell)| (city, cell.into_stats()))
+        .collect();
+    timer.phase("merge");
+    Ok(cities_stats)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lock_free::multi_thread, timer::PhaseTimer, ParseError, ParseOptions};
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn it_aggregates_across_threads() {
+        let content = "Hamburg;12.0\nIstanbul;6.2\nHamburg;-3.4\n".repeat(1_000);
+        let cities_stats: BTreeMap<_, _> = multi_thread(
+            content.as_bytes(),
+            4,
+            128,
+            ParseOptions::default(),
+            &mut PhaseTimer::new(),
+        )
+        .unwrap()
+        .into_iter()
+        .collect();
+        let hamburg = &cities_stats["Hamburg".as_bytes()];
+        assert_eq!((-34, 120), (hamburg.min, hamburg.max));
+        assert_eq!((2_000, 86_000), (hamburg.count, hamburg.sum));
+        assert_eq!(1_000, cities_stats["Istanbul".as_bytes()].count);
+    }
+
+    #[test]
+    fn it_reports_the_line_of_a_malformed_row() {
+        let content = "Hamburg;12.0\n".repeat(100) + "Hamburg 12.0\n" + "Oslo;1.0\n";
+        let err = multi_thread(
+            content.as_bytes(),
+            4,
+            128,
+            ParseOptions::default(),
+            &mut PhaseTimer::new(),
+        )
+        .unwrap_err();
+        assert_eq!(ParseError::MissingSeparator, err.err);
+        assert_eq!(101, err.line(content.as_bytes(), 1));
+    }
+
+    #[test]
+    fn it_reports_the_first_of_many_malformed_rows() {
+        let content = "Hamburg;12.0\n".repeat(100) + &"Hamburg 12.0\n".repeat(100);
+        for _ in 0..20 {
+            let err = multi_thread(
+                content.as_bytes(),
+                4,
+                16,
+                ParseOptions::default(),
+                &mut PhaseTimer::new(),
+            )
+            .unwrap_err();
+            assert_eq!(101, err.line(content.as_bytes(), 1));
+        }
+    }
+}
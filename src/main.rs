@@ -1,219 +1,753 @@
-use lazy_static::lazy_static;
+#[cfg(unix)]
+use memmap2::Advice;
 use memmap2::Mmap;
-use rustc_hash::{FxHashMap, FxHasher};
+#[cfg(feature = "gzip")]
+use onebrc::gzip::{self, StreamError};
+#[cfg(feature = "lock-free")]
+use onebrc::lock_free;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use onebrc::uring;
+use onebrc::{
+    args::{
+        Args, ArgsError, Format, GenerateArgs, Hasher, MergeArgs, SortKey, SortMode, Unit,
+        ValidateArgs, GENERATE_USAGE, MERGE_USAGE, USAGE, VALIDATE_USAGE,
+    },
+    check::check_lines,
+    count_comments, count_rows, find_invalid_city,
+    generate::generate,
+    merge::{merge_raw, MalformedRaw},
+    merge_maps, multi_thread,
+    output::{
+        exclude_stats, filter_stats, min_count_stats, sort_stats, stats_writer, top_n,
+        write_entries, write_histograms, Column, DatasetSummary, DefaultWriter, StatsWriter,
+        TableWriter, DEFAULT_COLUMNS,
+    },
+    skip_line, strip_bom,
+    timer::PhaseTimer,
+    unique_cities,
+    validate::{compare, parse_results, MalformedEntry},
+    AggregateError, ParseError, ParseOptions, RowError, Stats,
+};
+#[cfg(feature = "gzip")]
+use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashSet, FxHasher};
 use std::{
     collections::BTreeMap,
-    fs::File,
+    env,
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
     hash::BuildHasherDefault,
-    io::Write,
-    sync::mpsc::channel,
-    thread::{self, available_parallelism},
-    time::Instant,
-    usize,
+    hint,
+    io::{self, BufReader, BufWriter, IsTerminal, Read, Write},
+    ops::Deref,
+    path::Path,
+    process::ExitCode,
+    str,
+    thread::available_parallelism,
+    time::{Duration, Instant},
 };
+use terminal_size::{terminal_size_of, Width};
+
+/// Buffer for generated files and merged results.
+const OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Characters per histogram line when not printing to a terminal.
+const DEFAULT_HISTOGRAM_WIDTH: usize = 80;
+
+/// Input path that reads the measurements from stdin instead of a file.
+const STDIN_PATH: &str = "-";
 
-struct Stats {
-    min: i32,
-    max: i32,
-    sum: i32,
-    count: usize,
+/// Stride of the fallback warmup loop in [`prefault`]; touching one byte per
+/// 4 KiB page is enough to fault in the whole page.
+const PAGE_SIZE: usize = 4096;
+
+/// Everything that can end a run early, with the message shown for it.
+enum AppError {
+    Usage {
+        err: ArgsError,
+        usage: &'static str,
+    },
+    Open {
+        path: String,
+        err: io::Error,
+    },
+    Create {
+        path: String,
+        err: io::Error,
+    },
+    Write {
+        path: Option<String>,
+        err: io::Error,
+    },
+    /// A malformed row on line `line` of `path`.
+    MalformedInput {
+        path: String,
+        line: u64,
+        err: ParseError,
+    },
+    /// A city name that is not UTF-8, `offset` bytes into `path`.
+    InvalidUtf8 {
+        path: String,
+        offset: u64,
+    },
+    #[cfg(feature = "gzip")]
+    Read {
+        path: String,
+        err: io::Error,
+    },
+    MalformedReference {
+        path: String,
+        err: MalformedEntry,
+    },
+    MalformedRaw {
+        path: String,
+        err: MalformedRaw,
+    },
+    /// The worker threads could not be started.
+    Spawn(io::Error),
+    Discrepancies(usize),
+    MalformedLines(u64),
 }
 
-lazy_static! {
-    static ref BUFFER: Mmap =
-        unsafe { Mmap::map(&File::open("measurements.txt").unwrap()).unwrap() };
+impl AppError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            AppError::Usage { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    /// The error for a malformed row of `buffer`, the contents of `path`
+    /// after any skipped header.
+    fn malformed(path: &str, buffer: &[u8], options: ParseOptions, err: RowError) -> AppError {
+        AppError::MalformedInput {
+            path: path.to_string(),
+            line: err.line(buffer, options.first_line),
+            err: err.err,
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Usage { err, usage } => write!(f, "{err}\n\n{usage}"),
+            AppError::Open { path, err } => write!(f, "Cannot open '{path}': {err}"),
+            AppError::Create { path, err } => write!(f, "Cannot create '{path}': {err}"),
+            #[cfg(feature = "gzip")]
+            AppError::Read { path, err } => write!(f, "Cannot read '{path}': {err}"),
+            AppError::Write {
+                path: Some(path),
+                err,
+            } => write!(f, "Cannot write '{path}': {err}"),
+            AppError::Write { path: None, err } => write!(f, "Cannot write the results: {err}"),
+            AppError::MalformedInput { path, line, err } => {
+                write!(f, "Malformed input: line {line} of '{path}': {err}")
+            }
+            AppError::InvalidUtf8 { path, offset } => write!(
+                f,
+                "Malformed input: city name at byte {offset} of '{path}' is not UTF-8"
+            ),
+            AppError::MalformedReference { path, err } => {
+                write!(f, "Cannot parse '{path}': {err}")
+            }
+            AppError::MalformedRaw { path, err } => write!(f, "Cannot merge '{path}': {err}"),
+            AppError::Spawn(err) => write!(f, "Cannot start the worker threads: {err}"),
+            AppError::Discrepancies(count) => {
+                write!(f, "{count} cities differ from the reference")
+            }
+            AppError::MalformedLines(count) => write!(f, "{count} lines are malformed"),
+        }
+    }
 }
 
-fn main() {
-    let num_threads = 10 * available_parallelism().unwrap().get();
-    let (tx, rx) = channel();
-    let chunks = chunks(&BUFFER, num_threads);
-    let num_chunks = chunks.len();
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<(), AppError> {
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("generate") => {
+            args.next();
+            return run_generate(args);
+        }
+        Some("validate") => {
+            args.next();
+            return run_validate(args);
+        }
+        Some("merge") => {
+            args.next();
+            return run_merge(args);
+        }
+        _ => {}
+    }
+    let args = Args::parse(args).map_err(|err| AppError::Usage { err, usage: USAGE })?;
+    let mut timer = PhaseTimer::new();
+    // Compressed files are streamed instead.
+    let inputs = match args.gzip {
+        true => vec![],
+        false => open_prefaulted(&args, &mut timer)?,
+    };
+    let buffers: Vec<&[u8]> = inputs
+        .iter()
+        .map(|input| match args.skip_header {
+            true => skip_line(input),
+            false => strip_bom(input),
+        })
+        .collect();
+    let options = args.parse_options();
+    if args.line_count {
+        let rows = |buffer: &&[u8]| match options.allow_comments {
+            true => count_rows(buffer) - count_comments(buffer),
+            false => count_rows(buffer),
+        };
+        println!("{}", buffers.iter().map(rows).sum::<u64>());
+        return Ok(());
+    }
+    if args.unique_cities {
+        let mut cities = FxHashSet::default();
+        for (path, buffer) in args.paths.iter().zip(&buffers) {
+            let unique = unique_cities(buffer, options)
+                .map_err(|err| AppError::malformed(path, buffer, options, err))?;
+            cities.extend(unique);
+        }
+        println!("{}", cities.len());
+        return Ok(());
+    }
+    if args.check {
+        return run_check(&args, &buffers);
+    }
 
     let time = Instant::now();
-    for chunk in chunks {
-        let tx = tx.clone();
-        thread::spawn(move || {
-            let mut cities_stats: FxHashMap<&[u8], Stats> =
-                FxHashMap::with_capacity_and_hasher(100, BuildHasherDefault::<FxHasher>::default());
-            let mut i = 0;
-            while i < chunk.len() {
-                let (city, measure, last) = parse_next_row(&chunk[i..]);
-                let stats = cities_stats.entry(city).or_insert(Stats {
-                    min: i32::MAX,
-                    max: i32::MIN,
-                    sum: 0,
-                    count: 0,
-                });
-                stats.min = measure.min(stats.min);
-                stats.max = measure.max(stats.max);
-                stats.count += 1;
-                stats.sum += measure;
-                i += last;
-            }
-            tx.send(cities_stats).unwrap();
-        });
+    // Owns the names of the cities in compressed files, which are not in
+    // any of the buffers.
+    #[cfg(feature = "gzip")]
+    let mut decompressed_names = vec![];
+    // The bytes aggregated, for the throughput.
+    let (mut cities_stats, size) = match args.gzip {
+        #[cfg(feature = "gzip")]
+        true => {
+            let mut bytes = 0;
+            let cities_stats =
+                aggregate_gzip(&args, &mut decompressed_names, &mut bytes, &mut timer)?;
+            (cities_stats, InputSize::Decompressed(bytes))
+        }
+        _ => {
+            let bytes = buffers.iter().map(|buffer| buffer.len() as u64).sum();
+            let cities_stats = aggregate(&args, &buffers, &mut timer)?;
+            (cities_stats, InputSize::File(bytes))
+        }
+    };
+    timer.phase("sort");
+    // Counted before --filter/--exclude drop any rows.
+    let total_count: usize = cities_stats.iter().map(|(_, stats)| stats.count()).sum();
+    let summary = args.summary.then(|| DatasetSummary::new(&cities_stats));
+
+    let mut columns = args.select.clone().unwrap_or(DEFAULT_COLUMNS.to_vec());
+    // --count is redundant once count is selected, and would repeat it.
+    let count = args.count && !columns.contains(&Column::Count);
+    columns.extend(
+        [
+            count.then_some(Column::Count),
+            args.median.then_some(Column::Median),
+            args.stddev.then_some(Column::Stddev),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(args.percentiles.iter().map(|&p| Column::Percentile(p))),
+    );
+    if let Some(pattern) = &args.filter {
+        filter_stats(&mut cities_stats, pattern);
+    }
+    if let Some(pattern) = &args.exclude {
+        exclude_stats(&mut cities_stats, pattern);
+    }
+    if let Some(min_count) = args.min_count {
+        min_count_stats(&mut cities_stats, min_count);
+    }
+    match (args.sort_by, args.top_n) {
+        (Some(key), n) => {
+            sort_stats(&mut cities_stats, key);
+            cities_stats.truncate(n.unwrap_or(usize::MAX));
+        }
+        (None, Some(n)) => top_n(&mut cities_stats, n),
+        (None, None) => {}
     }
 
-    let mut i = 0;
-    let mut cities_stats: BTreeMap<&[u8], Stats> = BTreeMap::new();
-    while i < num_chunks {
-        if let Ok(work) = rx.recv() {
-            for (city, stats) in work {
-                if cities_stats.contains_key(city) {
-                    let global_stats = cities_stats.get_mut(city).unwrap();
-                    global_stats.min = stats.min.min(global_stats.min);
-                    global_stats.max = stats.max.max(global_stats.max);
-                    global_stats.sum += stats.sum;
-                    global_stats.count += stats.count;
-                } else {
-                    cities_stats.insert(city, stats);
-                }
+    if args.dry_run {
+        hint::black_box(&cities_stats);
+    } else {
+        write_results(&args, &columns, &cities_stats)?;
+    }
+    timer.phase("output");
+    let elapsed = time.elapsed();
+    if !args.no_timing {
+        eprintln!("{elapsed:?}");
+    }
+    if args.verbose {
+        // Like the timing above, this is best effort.
+        let _ = write_verbose_summary(&timer, size, total_count, elapsed);
+    }
+    if let Some(summary) = summary {
+        let _ = summary.write(&mut io::stderr().lock(), args.unit);
+    }
+    Ok(())
+}
+
+/// Opens every input and faults in the mapped ones.
+fn open_prefaulted(args: &Args, timer: &mut PhaseTimer) -> Result<Vec<Input>, AppError> {
+    let mut inputs = Vec::with_capacity(args.paths.len());
+    for path in &args.paths {
+        let input = open_input(args, path).map_err(|err| AppError::Open {
+            path: path.clone(),
+            err,
+        })?;
+        timer.phase("open");
+        match &input {
+            Input::Mapped(mmap) => {
+                prefault(mmap);
+                timer.phase("prefault");
             }
-            i += 1;
+            // Already in memory.
+            Input::Read(_) => {}
         }
+        inputs.push(input);
     }
+    Ok(inputs)
+}
 
-    let stdout = std::io::stdout();
-    let mut lock = stdout.lock();
-    write!(lock, "{{").unwrap();
-    let mut c = 0;
-    for (city, stats) in &cities_stats {
-        write!(
-            lock,
-            "{}={}/{:.2}/{}",
-            std::str::from_utf8(city).unwrap(),
-            stats.min as f32 / 10.0,
-            stats.sum as f32 / stats.count as f32 / 10.0,
-            stats.max as f32 / 10.0
-        )
-        .unwrap();
-        c += 1;
-        if c != cities_stats.len() {
-            write!(lock, ", ").unwrap();
-        }
-    }
-    write!(lock, "}}").unwrap();
-    writeln!(lock, "{:?}", time.elapsed()).unwrap();
-}
-
-#[inline(always)]
-fn chunks(buffer: &[u8], num_threads: usize) -> Vec<&[u8]> {
-    let mut result = vec![];
-    let chunk_size = buffer.len() / num_threads;
-    let mut i = 0;
-    while i <= buffer.len() {
-        let s = i;
-        i = if i + chunk_size < buffer.len() {
-            i + chunk_size
-        } else {
-            buffer.len()
+/// Aggregates the `buffers` of `args.paths` as one input and puts the
+/// cities in name order.
+fn aggregate<'a>(
+    args: &Args,
+    buffers: &[&'a [u8]],
+    timer: &mut PhaseTimer,
+) -> Result<Vec<(&'a [u8], Stats)>, AppError> {
+    let mut results = Vec::with_capacity(buffers.len());
+    for (path, buffer) in args.paths.iter().zip(buffers) {
+        results.push(aggregate_file(args, path, buffer, timer)?);
+    }
+    let mut cities_stats = results.pop().unwrap_or_default();
+    if !results.is_empty() {
+        let merged = results
+            .into_iter()
+            .map(|result| result.into_iter().collect())
+            .fold(cities_stats.into_iter().collect(), merge_maps);
+        cities_stats = merged.into_iter().collect();
+        timer.phase("merge files");
+    }
+    Ok(sort_by_name(args.sort_mode, cities_stats))
+}
+
+/// Decompresses and aggregates every gzip file in `args.paths`, then puts
+/// the cities in name order. The results borrow the city names from
+/// `names`, and the decompressed bytes are counted in `bytes`.
+#[cfg(feature = "gzip")]
+fn aggregate_gzip<'a>(
+    args: &Args,
+    names: &'a mut Vec<Vec<u8>>,
+    bytes: &mut u64,
+    timer: &mut PhaseTimer,
+) -> Result<Vec<(&'a [u8], Stats)>, AppError> {
+    let mut cities_stats = FxHashMap::default();
+    for path in &args.paths {
+        let reader: Box<dyn Read> = match path.as_str() {
+            STDIN_PATH => Box::new(io::stdin().lock()),
+            _ => match File::open(path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(err) => {
+                    return Err(AppError::Open {
+                        path: path.clone(),
+                        err,
+                    })
+                }
+            },
         };
-        while i < buffer.len() && buffer[i] != b'\n' {
-            i += 1;
-        }
-        result.push(&buffer[s..i]);
-        i += 1;
+        *bytes += gzip::aggregate_gzip(
+            reader,
+            &mut cities_stats,
+            args.extras(),
+            args.parse_options(),
+            args.skip_header,
+        )
+        .map_err(|err| match err {
+            StreamError::Io(err) => AppError::Read {
+                path: path.clone(),
+                err,
+            },
+            StreamError::Parse { line, err } => AppError::MalformedInput {
+                path: path.clone(),
+                line,
+                err,
+            },
+            StreamError::InvalidUtf8 { offset } => AppError::InvalidUtf8 {
+                path: path.clone(),
+                offset,
+            },
+        })?;
     }
+    timer.phase("gzip");
+    let stats: Vec<Stats>;
+    (*names, stats) = cities_stats.into_iter().unzip();
+    let cities_stats = names.iter().map(Vec::as_slice).zip(stats).collect();
+    Ok(sort_by_name(args.sort_mode, cities_stats))
+}
 
-    result
+fn sort_by_name(sort_mode: SortMode, mut cities_stats: Vec<(&[u8], Stats)>) -> Vec<(&[u8], Stats)> {
+    match sort_mode {
+        SortMode::BTree => {
+            let by_name: BTreeMap<_, _> = cities_stats.into_iter().collect();
+            by_name.into_iter().collect()
+        }
+        SortMode::Vec => {
+            sort_stats(&mut cities_stats, SortKey::Name);
+            cities_stats
+        }
+    }
 }
 
-#[inline(always)]
-fn parse_next_row(slice: &[u8]) -> (&[u8], i32, usize) {
-    let mut i = 0;
-    while slice[i] != b';' {
-        i += 1;
+/// Aggregates the `buffer` read from `path`, in no particular order.
+fn aggregate_file<'a>(
+    args: &Args,
+    path: &str,
+    buffer: &'a [u8],
+    timer: &mut PhaseTimer,
+) -> Result<Vec<(&'a [u8], Stats)>, AppError> {
+    // Nothing to split into chunks or hand to the workers.
+    if buffer.is_empty() {
+        return Ok(vec![]);
     }
-    let end_city = i;
-    i += 1;
-    let sign: i32 = if slice[i] == b'-' {
-        i += 1;
-        -1
-    } else {
-        1
+    let options = args.parse_options();
+    let num_threads = args
+        .threads
+        .unwrap_or_else(|| available_parallelism().map_or(4, |n| n.get()));
+    let result = match args.hasher {
+        #[cfg(feature = "lock-free")]
+        _ if args.lock_free => {
+            lock_free::multi_thread(buffer, num_threads, args.chunk_size, options, timer)
+                .map_err(AggregateError::Row)
+        }
+        Hasher::Fx => multi_thread(
+            buffer,
+            num_threads,
+            args.chunk_size,
+            args.extras(),
+            options,
+            BuildHasherDefault::<FxHasher>::default(),
+            timer,
+        ),
+        #[cfg(feature = "ahash")]
+        Hasher::Ahash => multi_thread(
+            buffer,
+            num_threads,
+            args.chunk_size,
+            args.extras(),
+            options,
+            ahash::RandomState::with_seeds(1, 2, 3, 4),
+            timer,
+        ),
     };
-    let mut measure = sign * (slice[i] - b'0') as i32;
-    i += 1;
-    if slice[i] != b'.' {
-        measure = measure * 10 + (slice[i] - b'0') as i32;
-        i += 1;
-    }
-    i += 1;
-    measure = 10 * measure + (slice[i] - b'0') as i32;
-    i += 1;
-
-    (&slice[0..end_city], measure, i + 1)
-}
-
-#[cfg(test)]
-mod test {
-    use crate::{chunks, parse_next_row};
-    use pretty_assertions::assert_eq;
-
-    fn content() -> &'static [u8] {
-        r#"Hamburg;12.0
-Bulawayo;8.9
-Palembang;38.8
-St. John's;15.2
-Cracow;12.6
-Bridgetown;26.9
-Istanbul;6.2
-Roseau;34.4
-Conakry;31.2
-Istanbul;23.0"#
-            .as_bytes()
-    }
-
-    #[test]
-    fn it_chunks_content() {
-        let content = content();
-        assert_eq!(
-            vec![
-                r#"Hamburg;12.0
-Bulawayo;8.9
-Palembang;38.8
-St. John's;15.2"#
-                    .as_bytes(),
-                r#"Cracow;12.6
-Bridgetown;26.9
-Istanbul;6.2
-Roseau;34.4"#
-                    .as_bytes(),
-                r#"Conakry;31.2
-Istanbul;23.0"#
-                    .as_bytes()
-            ],
-            chunks(content, 3)
-        );
-    }
-
-    #[test]
-    fn it_parses_row() {
-        let content = content();
-
-        let mut result: Vec<(&[u8], i32)> = vec![];
-        let mut i = 0;
-        while i < content.len() {
-            let (city, measure, last) = parse_next_row(&content[i..]);
-            result.push((city, measure));
-            i += last;
-        }
-
-        assert_eq!(
-            vec![
-                ("Hamburg".as_bytes(), 120),
-                ("Bulawayo".as_bytes(), 89),
-                ("Palembang".as_bytes(), 388),
-                ("St. John's".as_bytes(), 152),
-                ("Cracow".as_bytes(), 126),
-                ("Bridgetown".as_bytes(), 269),
-                ("Istanbul".as_bytes(), 62),
-                ("Roseau".as_bytes(), 344),
-                ("Conakry".as_bytes(), 312),
-                ("Istanbul".as_bytes(), 230),
-            ],
-            result
-        );
+    let cities_stats = result.map_err(|err| match err {
+        AggregateError::Row(err) => AppError::malformed(path, buffer, options, err),
+        AggregateError::Spawn(err) => AppError::Spawn(err),
+    })?;
+    // Checked once per city rather than per row, since names repeat.
+    if cities_stats
+        .iter()
+        .any(|(city, _)| str::from_utf8(city).is_err())
+    {
+        if let Some(offset) = find_invalid_city(buffer, options) {
+            return Err(AppError::InvalidUtf8 {
+                path: path.to_string(),
+                offset: offset as u64,
+            });
+        }
+    }
+    Ok(cities_stats)
+}
+
+fn run_generate<I: Iterator<Item = String>>(args: I) -> Result<(), AppError> {
+    let args = GenerateArgs::parse(args).map_err(|err| AppError::Usage {
+        err,
+        usage: GENERATE_USAGE,
+    })?;
+    let file = File::create(&args.output).map_err(|err| AppError::Create {
+        path: args.output.clone(),
+        err,
+    })?;
+    let mut out = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, file);
+    let seed = args.seed.unwrap_or_else(|| fastrand::u64(..));
+    generate(&mut out, args.rows, args.cities, seed)
+        .and_then(|()| out.flush())
+        .map_err(|err| AppError::Write {
+            path: Some(args.output),
+            err,
+        })
+}
+
+fn run_validate<I: Iterator<Item = String>>(args: I) -> Result<(), AppError> {
+    let usage = |err| AppError::Usage {
+        err,
+        usage: VALIDATE_USAGE,
+    };
+    let args = ValidateArgs::parse(args).map_err(usage)?;
+    let expected = fs::read_to_string(&args.expected).map_err(|err| AppError::Open {
+        path: args.expected.clone(),
+        err,
+    })?;
+    let expected = parse_results(&expected).map_err(|err| AppError::MalformedReference {
+        path: args.expected.clone(),
+        err,
+    })?;
+    let run_args = Args::parse([args.input]).map_err(usage)?;
+    let mut timer = PhaseTimer::new();
+    let inputs = open_prefaulted(&run_args, &mut timer)?;
+    let buffers: Vec<&[u8]> = inputs.iter().map(|input| strip_bom(input)).collect();
+    let cities_stats = aggregate(&run_args, &buffers, &mut timer)?;
+    let mut out = vec![];
+    write_entries(
+        &mut DefaultWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+        &cities_stats,
+    )
+    .map_err(|err| AppError::Write { path: None, err })?;
+    // Fails only for city names containing ", ", which the format cannot
+    // tell apart from the separator.
+    let actual = parse_results(&String::from_utf8_lossy(&out)).map_err(|err| {
+        AppError::MalformedReference {
+            path: run_args.paths[0].clone(),
+            err,
+        }
+    })?;
+
+    let discrepancies = compare(&expected, &actual);
+    for discrepancy in &discrepancies {
+        println!("{discrepancy}");
     }
+    match discrepancies.len() {
+        0 => Ok(()),
+        count => Err(AppError::Discrepancies(count)),
+    }
+}
+
+/// Prints the results of the `--format raw` files as one run.
+fn run_merge<I: Iterator<Item = String>>(args: I) -> Result<(), AppError> {
+    let args = MergeArgs::parse(args).map_err(|err| AppError::Usage {
+        err,
+        usage: MERGE_USAGE,
+    })?;
+    let mut merged = BTreeMap::new();
+    for path in &args.paths {
+        let raw = fs::read(path).map_err(|err| AppError::Open {
+            path: path.clone(),
+            err,
+        })?;
+        merge_raw(&raw, &mut merged).map_err(|err| AppError::MalformedRaw {
+            path: path.clone(),
+            err,
+        })?;
+    }
+    let cities_stats: Vec<(&[u8], Stats)> = merged
+        .iter()
+        .map(|(city, stats)| (&city[..], stats.clone()))
+        .collect();
+    let mut out = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, io::stdout().lock());
+    write_entries(
+        &mut DefaultWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+        &cities_stats,
+    )
+    .and_then(|()| out.flush())
+    .map_err(|err| AppError::Write { path: None, err })
+}
+
+/// Checks every line of the `buffers` read from the input paths, like a run
+/// would parse them, and reports all the malformed ones.
+fn run_check(args: &Args, buffers: &[&[u8]]) -> Result<(), AppError> {
+    let options = args.parse_options();
+    let (mut lines, mut errors) = (0, 0);
+    for (path, buffer) in args.paths.iter().zip(buffers) {
+        lines += check_lines(buffer, options, |line, err| {
+            errors += 1;
+            eprintln!("line {line} of '{path}': {err}");
+        });
+    }
+    println!("{lines} lines checked, {errors} errors");
+    match errors {
+        0 => Ok(()),
+        count => Err(AppError::MalformedLines(count)),
+    }
+}
+
+/// The measurements file, either mapped or already read into memory.
+enum Input {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Mapped(mmap) => mmap,
+            Input::Read(buffer) => buffer,
+        }
+    }
+}
+
+fn open_input(args: &Args, path: &str) -> io::Result<Input> {
+    if path == STDIN_PATH {
+        let mut buffer = vec![];
+        io::stdin().lock().read_to_end(&mut buffer)?;
+        return Ok(Input::Read(buffer));
+    }
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    if args.io_uring {
+        return uring::read_file(path).map(Input::Read);
+    }
+    if args.no_mmap {
+        return read_file_to_vec(Path::new(&path)).map(Input::Read);
+    }
+    let file = File::open(path)?;
+    let mmap = unsafe { Mmap::map(&file) }?;
+    // Workers sweep the file front to back, so ask for aggressive read-ahead
+    // and start faulting pages in before the threads are spawned. Both are
+    // only hints; kernels that reject them are no worse off.
+    #[cfg(unix)]
+    {
+        let _ = mmap.advise(Advice::Sequential);
+        let _ = mmap.advise(Advice::WillNeed);
+    }
+    // Unlike the hints above this was asked for, so say when it is refused.
+    // Whether it takes effect also depends on
+    // /sys/kernel/mm/transparent_hugepage/enabled allowing `madvise`.
+    #[cfg(target_os = "linux")]
+    if args.huge_pages {
+        if let Err(err) = mmap.advise(Advice::HugePage) {
+            eprintln!("Cannot enable huge pages: {err}");
+        }
+    }
+    Ok(Input::Mapped(mmap))
+}
+
+/// Reads all of `path` through a `BufReader`, for file systems where mapping
+/// is slow or unsupported.
+fn read_file_to_vec(path: &Path) -> io::Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let mut buffer = Vec::with_capacity(file.metadata()?.len() as usize);
+    BufReader::new(file).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Faults in every page of the mapping so the workers do not stall on page
+/// faults. Uses `MADV_POPULATE_READ` where available (Linux 5.14+) and falls
+/// back to reading one byte per page when the kernel rejects it with `EINVAL`.
+fn prefault(mmap: &Mmap) {
+    #[cfg(target_os = "linux")]
+    match mmap.advise(Advice::PopulateRead) {
+        Err(err) if err.kind() == io::ErrorKind::InvalidInput => {}
+        _ => return,
+    }
+    let sum = mmap
+        .iter()
+        .step_by(PAGE_SIZE)
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    hint::black_box(sum);
+}
+
+fn write_results(
+    args: &Args,
+    columns: &[Column],
+    cities_stats: &[(&[u8], Stats)],
+) -> Result<(), AppError> {
+    let stdout = std::io::stdout();
+    // Stdout on its own is line buffered, which costs a syscall per entry
+    // in the line based formats.
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::with_capacity(args.output_buffer_size, file)),
+            Err(err) => {
+                return Err(AppError::Create {
+                    path: path.clone(),
+                    err,
+                })
+            }
+        },
+        None => Box::new(BufWriter::with_capacity(
+            args.output_buffer_size,
+            stdout.lock(),
+        )),
+    };
+    let color = args.color(stdout.is_terminal(), env::var_os("NO_COLOR").as_deref());
+    // Only a terminal has a width to fit the table and the histograms in.
+    let width = match &args.output {
+        None => terminal_size_of(&stdout).map(|(Width(width), _)| width as usize),
+        Some(_) => None,
+    };
+    let mut writer: Box<dyn StatsWriter> = match (args.one_per_line, color) {
+        (true, _) => {
+            Box::new(DefaultWriter::one_per_line(&mut out, columns, args.unit).with_color(color))
+        }
+        (false, true) => {
+            Box::new(DefaultWriter::new(&mut out, columns, args.unit).with_color(true))
+        }
+        (false, false) if args.format == Format::Table => {
+            Box::new(TableWriter::new(&mut out, columns, args.unit).with_max_width(width))
+        }
+        (false, false) => stats_writer(&args.format, &mut out, columns, args.unit),
+    };
+    let written = write_entries(&mut *writer, cities_stats);
+    drop(writer);
+    written
+        .and_then(|()| match args.ascii_histogram {
+            true => write_histograms(
+                &mut out,
+                cities_stats,
+                width.unwrap_or(DEFAULT_HISTOGRAM_WIDTH),
+            ),
+            false => Ok(()),
+        })
+        .and_then(|()| out.flush())
+        .map_err(|err| AppError::Write {
+            path: args.output.clone(),
+            err,
+        })
+}
+
+/// How much input a run aggregated.
+#[derive(Clone, Copy)]
+enum InputSize {
+    /// Bytes of the input files after any header.
+    File(u64),
+    /// Bytes of the gzip files once decompressed.
+    #[cfg(feature = "gzip")]
+    Decompressed(u64),
+}
+
+fn write_verbose_summary(
+    timer: &PhaseTimer,
+    size: InputSize,
+    total_count: usize,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let mut err = io::stderr().lock();
+    timer.write_summary(&mut err)?;
+    let (label, bytes) = match size {
+        InputSize::File(bytes) => ("file size", bytes),
+        #[cfg(feature = "gzip")]
+        InputSize::Decompressed(bytes) => ("decompressed", bytes),
+    };
+    let seconds = elapsed.as_secs_f64();
+    let megabytes_per_second = bytes as f64 / seconds / 1e6;
+    let rows_per_second = total_count as f64 / seconds;
+    writeln!(err, "{label:<13}{:.2} MB", bytes as f64 / 1e6)?;
+    writeln!(err, "rows         {total_count}")?;
+    writeln!(err, "throughput   {megabytes_per_second:.2} MB/s")?;
+    writeln!(err, "             {rows_per_second:.0} rows/s")
 }
@@ -0,0 +1,107 @@
+use crate::Stats;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// A line of raw results that is not `city\tsum\tcount\tmin\tmax`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MalformedRaw {
+    pub line: u64,
+}
+
+impl Display for MalformedRaw {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} is not city, sum, count, min and max", self.line)
+    }
+}
+
+/// Merges the cities of `raw`, the output of `--format raw`, into
+/// `cities_stats`. The city is everything before the last four tabs.
+pub fn merge_raw(
+    raw: &[u8],
+    cities_stats: &mut BTreeMap<Vec<u8>, Stats>,
+) -> Result<(), MalformedRaw> {
+    if raw.is_empty() {
+        return Ok(());
+    }
+    let lines = raw.strip_suffix(b"\n").unwrap_or(raw);
+    for (line, number) in lines.split(|&b| b == b'\n').zip(1..) {
+        let (city, stats) = parse_raw_line(line).ok_or(MalformedRaw { line: number })?;
+        match cities_stats.get_mut(city) {
+            Some(into) => into.merge(stats),
+            None => {
+                cities_stats.insert(city.to_vec(), stats);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_raw_line(line: &[u8]) -> Option<(&[u8], Stats)> {
+    let mut fields = line.rsplitn(5, |&b| b == b'\t');
+    let mut next = || {
+        fields
+            .next()
+            .and_then(|field| std::str::from_utf8(field).ok())
+    };
+    let max = next()?.parse().ok()?;
+    let min = next()?.parse().ok()?;
+    let count = next()?.parse().ok()?;
+    let sum = next()?.parse().ok()?;
+    let city = fields.next()?;
+    (count > 0 && min <= max).then(|| (city, Stats::from_raw(sum, count, min, max)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        merge::{merge_raw, MalformedRaw},
+        Stats,
+    };
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn it_merges_raw_results() {
+        let mut cities_stats = BTreeMap::new();
+        merge_raw(
+            b"Hamburg\t120\t1\t120\t120\nOslo\t-30\t2\t-20\t-10\n",
+            &mut cities_stats,
+        )
+        .unwrap();
+        merge_raw(
+            b"Oslo\t50\t1\t50\t50\nTab\tCity\t1\t1\t1\t1",
+            &mut cities_stats,
+        )
+        .unwrap();
+        merge_raw(b"", &mut cities_stats).unwrap();
+        assert_eq!(
+            vec![
+                (b"Hamburg".to_vec(), Stats::from_raw(120, 1, 120, 120)),
+                (b"Oslo".to_vec(), Stats::from_raw(20, 3, -20, 50)),
+                (b"Tab\tCity".to_vec(), Stats::from_raw(1, 1, 1, 1)),
+            ],
+            cities_stats.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_lines() {
+        let merge = |raw: &[u8]| merge_raw(raw, &mut BTreeMap::new());
+        assert_eq!(
+            Err(MalformedRaw { line: 2 }),
+            merge(b"Oslo\t1\t1\t1\t1\nOslo\t1\t1\t1\n")
+        );
+        assert_eq!(Err(MalformedRaw { line: 1 }), merge(b"Oslo\t1\t0\t1\t1\n"));
+        assert_eq!(Err(MalformedRaw { line: 1 }), merge(b"Oslo\t1\t2\t2\t1\n"));
+        assert_eq!(
+            Err(MalformedRaw { line: 1 }),
+            merge(b"Oslo\t1.0\t1\t1\t1\n")
+        );
+        assert_eq!(
+            Err(MalformedRaw { line: 1 }),
+            merge(b"{Oslo=1.0/1.0/1.0}\n")
+        );
+    }
+}
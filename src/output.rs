@@ -0,0 +1,1610 @@
+use crate::{
+    args::{Format, SortKey, Unit},
+    histogram::Histogram,
+    Stats,
+};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+};
+
+/// A temperature in tenths of a degree, written with one decimal without
+/// going through floating point.
+pub struct Tenths(pub i32);
+
+impl Display for Tenths {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let tenths = self.0.unsigned_abs();
+        write!(f, "{sign}{}.{}", tenths / 10, tenths % 10)
+    }
+}
+
+impl Unit {
+    /// `tenths` of a degree Celsius in this unit, rounded to the nearest
+    /// tenth. Nine fifths of a whole number of tenths are never a tie.
+    pub fn tenths(self, tenths: i32) -> i32 {
+        match self {
+            Unit::Celsius => tenths,
+            Unit::Fahrenheit => {
+                let scaled = (tenths * 9).abs() * 2 + 5;
+                tenths.signum() * (scaled / 10) + 320
+            }
+        }
+    }
+
+    /// Written after every temperature in the default format.
+    fn suffix(self) -> &'static str {
+        match self {
+            Unit::Celsius => "",
+            Unit::Fahrenheit => "°F",
+        }
+    }
+}
+
+/// The sum of the measurements in hundredths of a degree of `unit`. In
+/// Fahrenheit, every measurement adds 1.8 times itself plus 32 degrees.
+fn total_hundredths(stats: &Stats, unit: Unit) -> i64 {
+    match unit {
+        Unit::Celsius => 10 * stats.sum,
+        Unit::Fahrenheit => 18 * stats.sum + 3200 * stats.count as i64,
+    }
+}
+
+/// Mean temperature of a city with two decimals, computed with integer
+/// arithmetic only: hundredths of a degree are |sum| / 10 / count * 100,
+/// rounded half away from zero. In Fahrenheit, that is 18 * sum / count
+/// plus 3200.
+struct Mean<'a>(&'a Stats, Unit);
+
+impl Display for Mean<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let count = self.0.count as i64;
+        let total = total_hundredths(self.0, self.1);
+        let avg = (total.abs() * 2 + count) / (2 * count);
+        let sign = if total < 0 && avg != 0 { "-" } else { "" };
+        write!(f, "{sign}{}.{:02}", avg / 100, avg % 100)
+    }
+}
+
+/// A statistic printed for every city, in the order given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Min,
+    Avg,
+    Max,
+    /// Sum of the measurements, with one decimal.
+    Sum,
+    /// Number of measurements.
+    Count,
+    /// Median estimated from the city's reservoir sample.
+    Median,
+    /// Population standard deviation.
+    Stddev,
+    /// The given percentile (0 to 100), estimated like the median.
+    Percentile(u8),
+}
+
+/// The columns printed unless `--select` picks others.
+pub const DEFAULT_COLUMNS: &[Column] = &[Column::Min, Column::Avg, Column::Max];
+
+impl Column {
+    fn name(self) -> Cow<'static, str> {
+        match self {
+            Column::Min => "min".into(),
+            Column::Avg => "avg".into(),
+            Column::Max => "max".into(),
+            Column::Sum => "sum".into(),
+            Column::Count => "count".into(),
+            Column::Median => "median".into(),
+            Column::Stddev => "stddev".into(),
+            Column::Percentile(p) => format!("p{p}").into(),
+        }
+    }
+
+    /// The value as a plain number, valid in every output format.
+    fn value(self, stats: &Stats, unit: Unit) -> Value<'_> {
+        Value(self, stats, unit)
+    }
+
+    /// The unit of the value, in the default format.
+    fn suffix(self, unit: Unit) -> &'static str {
+        match self {
+            Column::Count => "",
+            _ => unit.suffix(),
+        }
+    }
+
+    /// The foreground color of the value on a terminal, if any.
+    fn color(self) -> Option<u8> {
+        match self {
+            Column::Min => Some(CYAN),
+            Column::Avg => Some(GREEN),
+            Column::Max => Some(RED),
+            _ => None,
+        }
+    }
+}
+
+const RED: u8 = 31;
+const GREEN: u8 = 32;
+const CYAN: u8 = 36;
+
+/// Starts bold white text, if `color` is set.
+fn ansi_bold(color: bool) -> &'static str {
+    if color {
+        "\x1b[1;37m"
+    } else {
+        ""
+    }
+}
+
+/// Starts text in the foreground color `code`, if `color` is set.
+fn ansi_fg(color: bool, code: u8) -> String {
+    if color {
+        format!("\x1b[{code}m")
+    } else {
+        String::new()
+    }
+}
+
+/// Ends the text started by [`ansi_bold`] or [`ansi_fg`].
+fn ansi_reset(color: bool) -> &'static str {
+    if color {
+        "\x1b[0m"
+    } else {
+        ""
+    }
+}
+
+/// A column of one city, formatted on demand.
+struct Value<'a>(Column, &'a Stats, Unit);
+
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Value(column, stats, unit) = *self;
+        // Degrees Fahrenheit per degree Celsius.
+        let scale = match unit {
+            Unit::Celsius => 1.0,
+            Unit::Fahrenheit => 1.8,
+        };
+        match column {
+            Column::Min => write!(f, "{}", Tenths(unit.tenths(stats.min))),
+            Column::Avg => write!(f, "{}", Mean(stats, unit)),
+            Column::Max => write!(f, "{}", Tenths(unit.tenths(stats.max))),
+            Column::Sum => {
+                let total = total_hundredths(stats, unit);
+                let tenths = (total.abs() + 5) / 10;
+                let sign = if total < 0 && tenths != 0 { "-" } else { "" };
+                write!(f, "{sign}{}.{}", tenths / 10, tenths % 10)
+            }
+            Column::Count => write!(f, "{}", stats.count),
+            Column::Median | Column::Percentile(_) => {
+                let reservoir = stats
+                    .reservoir
+                    .as_ref()
+                    .expect("quantiles need a reservoir");
+                let quantile = match column {
+                    Column::Percentile(p) => reservoir.quantile(p as f64 / 100.0),
+                    _ => reservoir.median(),
+                };
+                let offset = match unit {
+                    Unit::Celsius => 0.0,
+                    Unit::Fahrenheit => 32.0,
+                };
+                write!(f, "{:.1}", quantile / 10.0 * scale + offset)
+            }
+            Column::Stddev => {
+                let count = stats.count as f64;
+                let mean = stats.sum as f64 / count;
+                let variance = (stats.sum_sq as f64 / count - mean * mean).max(0.0);
+                write!(f, "{:.2}", variance.sqrt() / 10.0 * scale)
+            }
+        }
+    }
+}
+
+pub fn write_stats<W: Write>(
+    out: &mut W,
+    city: &[u8],
+    stats: &Stats,
+    columns: &[Column],
+    unit: Unit,
+) -> io::Result<()> {
+    write_colored_stats(out, city, stats, columns, unit, false)
+}
+
+/// [`write_stats`] with the city and, if `color` is set, min, avg and max
+/// in the colors of a terminal.
+fn write_colored_stats<W: Write>(
+    out: &mut W,
+    city: &[u8],
+    stats: &Stats,
+    columns: &[Column],
+    unit: Unit,
+    color: bool,
+) -> io::Result<()> {
+    write!(
+        out,
+        "{}{}{}=",
+        ansi_bold(color),
+        String::from_utf8_lossy(city),
+        ansi_reset(color)
+    )?;
+    for (i, column) in columns.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "/" };
+        let value = column.value(stats, unit);
+        let suffix = column.suffix(unit);
+        match column.color() {
+            Some(code) => write!(
+                out,
+                "{separator}{}{value}{suffix}{}",
+                ansi_fg(color, code),
+                ansi_reset(color)
+            )?,
+            None => write!(out, "{separator}{value}{suffix}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Keeps only the cities whose name contains `pattern`, ignoring case.
+pub fn filter_stats(entries: &mut Vec<(&[u8], Stats)>, pattern: &str) {
+    let pattern = pattern.to_lowercase();
+    entries.retain(|(city, _)| contains_ignore_case(city, &pattern));
+}
+
+/// Drops the cities whose name contains `pattern`, ignoring case.
+pub fn exclude_stats(entries: &mut Vec<(&[u8], Stats)>, pattern: &str) {
+    let pattern = pattern.to_lowercase();
+    entries.retain(|(city, _)| !contains_ignore_case(city, &pattern));
+}
+
+/// Drops the cities with fewer than `min_count` measurements.
+pub fn min_count_stats(entries: &mut Vec<(&[u8], Stats)>, min_count: usize) {
+    entries.retain(|(_, stats)| stats.count >= min_count);
+}
+
+fn contains_ignore_case(city: &[u8], lowercase_pattern: &str) -> bool {
+    String::from_utf8_lossy(city)
+        .to_lowercase()
+        .contains(lowercase_pattern)
+}
+
+/// Orders the entries by `key`: ascending for the city name, descending for
+/// every statistic, with ties broken alphabetically.
+pub fn sort_stats(entries: &mut [(&[u8], Stats)], key: SortKey) {
+    entries.sort_unstable_by(|a, b| compare(a, b, key));
+}
+
+/// Dataset-wide totals, merged from the stats of every city.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DatasetSummary {
+    pub cities: usize,
+    /// All measurements as if they were one city's, `None` without any.
+    pub total: Option<Stats>,
+}
+
+impl DatasetSummary {
+    pub fn new(cities_stats: &[(&[u8], Stats)]) -> Self {
+        let total = cities_stats
+            .iter()
+            .map(|(_, stats)| Stats {
+                reservoir: None,
+                histogram: None,
+                ..*stats
+            })
+            .reduce(|mut total, stats| {
+                total.merge(stats);
+                total
+            });
+        DatasetSummary {
+            cities: cities_stats.len(),
+            total,
+        }
+    }
+
+    /// Writes one aligned line per figure. The mean is over measurements,
+    /// not over the means of the cities.
+    pub fn write<W: Write>(&self, out: &mut W, unit: Unit) -> io::Result<()> {
+        let rows = self.total.as_ref().map_or(0, |total| total.count);
+        writeln!(out, "rows         {rows}")?;
+        writeln!(out, "cities       {}", self.cities)?;
+        if let Some(total) = &self.total {
+            let suffix = unit.suffix();
+            writeln!(
+                out,
+                "min          {}{suffix}",
+                Tenths(unit.tenths(total.min))
+            )?;
+            writeln!(
+                out,
+                "max          {}{suffix}",
+                Tenths(unit.tenths(total.max))
+            )?;
+            writeln!(out, "mean         {}{suffix}", Mean(total, unit))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the histogram of every city under its name: a `█` bar per 10°C
+/// bin from the lowest to the highest one with any measurement, followed
+/// by its count. Bars are scaled so that the fullest bin of the city takes
+/// the whole `width`, and any bin with a measurement gets at least one.
+pub fn write_histograms<W: Write>(
+    out: &mut W,
+    cities_stats: &[(&[u8], Stats)],
+    width: usize,
+) -> io::Result<()> {
+    for (city, stats) in cities_stats {
+        let histogram = stats
+            .histogram
+            .as_ref()
+            .expect("--ascii-histogram needs counts");
+        let bins = histogram.bins();
+        writeln!(out, "{}", String::from_utf8_lossy(city))?;
+        let Some(first) = bins.iter().position(|&count| count > 0) else {
+            continue;
+        };
+        let last = bins.iter().rposition(|&count| count > 0).unwrap_or(first);
+        let max = *bins.iter().max().unwrap_or(&0) as usize;
+        // `  -100..-90  ` before the bar and ` count` after it.
+        let bar_width = width.saturating_sub(13 + 1 + max.to_string().len()).max(1);
+        for (i, &count) in bins.iter().enumerate().take(last + 1).skip(first) {
+            let lower = Histogram::lower_bound(i);
+            let bar = (count as usize * bar_width).div_ceil(max);
+            writeln!(
+                out,
+                "  {lower:>4}..{:<4} {} {count}",
+                lower + 10,
+                "█".repeat(bar)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Keeps only the `n` entries with the highest maximum, ordered by it.
+/// Uses a partial selection so that only the kept entries get sorted.
+pub fn top_n(entries: &mut Vec<(&[u8], Stats)>, n: usize) {
+    if n < entries.len() {
+        entries.select_nth_unstable_by(n, |a, b| compare(a, b, SortKey::Max));
+        entries.truncate(n);
+    }
+    sort_stats(entries, SortKey::Max);
+}
+
+fn compare((a_city, a): &(&[u8], Stats), (b_city, b): &(&[u8], Stats), key: SortKey) -> Ordering {
+    let by_key = match key {
+        SortKey::Name => Ordering::Equal,
+        SortKey::Min => b.min.cmp(&a.min),
+        SortKey::Max => b.max.cmp(&a.max),
+        // Compares the exact means sum / count by cross-multiplying.
+        SortKey::Avg => (b.sum as i128 * a.count as i128).cmp(&(a.sum as i128 * b.count as i128)),
+        SortKey::Count => b.count.cmp(&a.count),
+    };
+    by_key.then_with(|| a_city.cmp(b_city))
+}
+
+/// An output format, written one city at a time in the order given.
+pub trait StatsWriter {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()>;
+
+    /// Closes the output after the last entry, or writes an empty document if
+    /// there was none.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Writes every entry of `cities_stats` to `writer` and finishes it.
+pub fn write_entries(
+    writer: &mut dyn StatsWriter,
+    cities_stats: &[(&[u8], Stats)],
+) -> io::Result<()> {
+    for (city, stats) in cities_stats {
+        writer.write_entry(city, stats)?;
+    }
+    writer.finish()
+}
+
+/// The writer for `format`, appending `columns` after min/avg/max, with
+/// temperatures in `unit`.
+pub fn stats_writer<'a, W: Write + 'a>(
+    format: &Format,
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+) -> Box<dyn StatsWriter + 'a> {
+    match format {
+        Format::Default => Box::new(DefaultWriter::new(out, columns, unit)),
+        Format::Json => Box::new(JsonWriter::new(out, columns, unit)),
+        Format::Ndjson => Box::new(NdjsonWriter::new(out, columns, unit)),
+        Format::Csv => Box::new(CsvWriter::new(out, columns, unit)),
+        Format::Tsv => Box::new(TsvWriter::new(out, columns, unit)),
+        Format::Prometheus => Box::new(PrometheusWriter::new(out, columns, unit)),
+        Format::Table => Box::new(TableWriter::new(out, columns, unit)),
+        Format::Raw => Box::new(RawWriter::new(out)),
+    }
+}
+
+/// `{city=min/avg/max, ...}` on one line, or whatever the columns are.
+pub struct DefaultWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+    started: bool,
+    one_per_line: bool,
+    color: bool,
+}
+
+impl<'a, W: Write> DefaultWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        DefaultWriter {
+            out,
+            columns,
+            unit,
+            started: false,
+            one_per_line: false,
+            color: false,
+        }
+    }
+
+    /// Colors the city names and temperatures with ANSI escapes if `color`
+    /// is set, for a terminal.
+    pub fn with_color(self, color: bool) -> Self {
+        DefaultWriter { color, ..self }
+    }
+
+    /// A `city=min/avg/max` line per city, without braces, for line based
+    /// tools. Writes nothing without any city.
+    pub fn one_per_line(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        DefaultWriter {
+            one_per_line: true,
+            ..Self::new(out, columns, unit)
+        }
+    }
+}
+
+impl<W: Write> StatsWriter for DefaultWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        let (columns, unit, color) = (self.columns, self.unit, self.color);
+        if self.one_per_line {
+            write_colored_stats(&mut self.out, city, stats, columns, unit, color)?;
+            return writeln!(self.out);
+        }
+        let separator = if self.started { ", " } else { "{" };
+        self.started = true;
+        write!(self.out, "{separator}")?;
+        write_colored_stats(&mut self.out, city, stats, columns, unit, color)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.one_per_line {
+            return Ok(());
+        }
+        if !self.started {
+            write!(self.out, "{{")?;
+        }
+        writeln!(self.out, "}}")
+    }
+}
+
+/// A JSON array with one object per city. Temperatures are JSON numbers.
+pub struct JsonWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+    started: bool,
+}
+
+impl<'a, W: Write> JsonWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        JsonWriter {
+            out,
+            columns,
+            unit,
+            started: false,
+        }
+    }
+}
+
+impl<W: Write> StatsWriter for JsonWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        let separator = if self.started { "," } else { "[" };
+        self.started = true;
+        write!(self.out, "{separator}")?;
+        write_json_object(&mut self.out, city, stats, self.columns, self.unit)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.started {
+            write!(self.out, "[")?;
+        }
+        writeln!(self.out, "]")
+    }
+}
+
+/// One standalone JSON object per line, in the same shape as the elements
+/// written by [`JsonWriter`].
+pub struct NdjsonWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+}
+
+impl<'a, W: Write> NdjsonWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        NdjsonWriter { out, columns, unit }
+    }
+}
+
+impl<W: Write> StatsWriter for NdjsonWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        write_json_object(&mut self.out, city, stats, self.columns, self.unit)?;
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn write_json_object<W: Write>(
+    out: &mut W,
+    city: &[u8],
+    stats: &Stats,
+    columns: &[Column],
+    unit: Unit,
+) -> io::Result<()> {
+    write!(out, "{{\"city\":")?;
+    write_json_string(out, &String::from_utf8_lossy(city))?;
+    for column in columns {
+        write!(out, ",\"{}\":{}", column.name(), column.value(stats, unit))?;
+    }
+    write!(out, "}}")
+}
+
+fn write_json_string<W: Write>(out: &mut W, value: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
+
+/// CSV with a `city,min,avg,max` header. City names containing a comma,
+/// quote or line break are quoted as per RFC 4180.
+pub struct CsvWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+    started: bool,
+}
+
+impl<'a, W: Write> CsvWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        CsvWriter {
+            out,
+            columns,
+            unit,
+            started: false,
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.started = true;
+        write!(self.out, "city")?;
+        for column in self.columns {
+            write!(self.out, ",{}", column.name())?;
+        }
+        writeln!(self.out)
+    }
+}
+
+impl<W: Write> StatsWriter for CsvWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+        let city = String::from_utf8_lossy(city);
+        if city.contains([',', '"', '\n', '\r']) {
+            write!(self.out, "\"{}\"", city.replace('"', "\"\""))?;
+        } else {
+            write!(self.out, "{city}")?;
+        }
+        for column in self.columns {
+            write!(self.out, ",{}", column.value(stats, self.unit))?;
+        }
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+        Ok(())
+    }
+}
+
+/// One tab separated `city, min, avg, max` line per city, without a header.
+/// City names are written verbatim since 1BRC names never contain tabs.
+pub struct TsvWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+}
+
+impl<'a, W: Write> TsvWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        TsvWriter { out, columns, unit }
+    }
+}
+
+impl<W: Write> StatsWriter for TsvWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        debug_assert!(!city.contains(&b'\t'), "city name contains a tab");
+        write!(self.out, "{}", String::from_utf8_lossy(city))?;
+        for column in self.columns {
+            write!(self.out, "\t{}", column.value(stats, self.unit))?;
+        }
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A table with the city names aligned left and the columns aligned right,
+/// each as wide as its widest value, under a header ruled with `─`. The
+/// rows are kept until the end to measure them.
+pub struct TableWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+    /// Characters a row may take, e.g. the width of the terminal.
+    max_width: Option<usize>,
+    rows: Vec<(String, Vec<String>)>,
+}
+
+/// Characters between two columns of a table.
+const TABLE_GAP: &str = "  ";
+
+impl<'a, W: Write> TableWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        TableWriter {
+            out,
+            columns,
+            unit,
+            max_width: None,
+            rows: vec![],
+        }
+    }
+
+    /// Truncates the city names with `…` where a row would be wider than
+    /// `max_width`, down to the width of the `city` header.
+    pub fn with_max_width(self, max_width: Option<usize>) -> Self {
+        TableWriter { max_width, ..self }
+    }
+}
+
+impl<W: Write> StatsWriter for TableWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        let values = self
+            .columns
+            .iter()
+            .map(|column| column.value(stats, self.unit).to_string())
+            .collect();
+        self.rows
+            .push((String::from_utf8_lossy(city).into_owned(), values));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let width = |value: &str| value.chars().count();
+        let mut widths: Vec<usize> = self.columns.iter().map(|c| c.name().len()).collect();
+        let mut city_width = "city".len();
+        for (city, values) in &self.rows {
+            city_width = city_width.max(width(city));
+            for (column_width, value) in widths.iter_mut().zip(values) {
+                *column_width = (*column_width).max(width(value));
+            }
+        }
+        if let Some(max_width) = self.max_width {
+            let numbers: usize = widths.iter().map(|w| TABLE_GAP.len() + w).sum();
+            city_width = city_width.min(max_width.saturating_sub(numbers).max("city".len()));
+        }
+
+        write!(self.out, "{:<city_width$}", "city")?;
+        for (column, width) in self.columns.iter().zip(&widths) {
+            write!(self.out, "{TABLE_GAP}{:>width$}", column.name())?;
+        }
+        writeln!(self.out)?;
+        write!(self.out, "{}", "─".repeat(city_width))?;
+        for width in &widths {
+            write!(self.out, "{TABLE_GAP}{}", "─".repeat(*width))?;
+        }
+        writeln!(self.out)?;
+        for (city, values) in &self.rows {
+            if width(city) > city_width {
+                let kept: String = city.chars().take(city_width - 1).collect();
+                write!(self.out, "{kept}…")?;
+            } else {
+                write!(self.out, "{city:<city_width$}")?;
+            }
+            for (value, width) in values.iter().zip(&widths) {
+                write!(self.out, "{TABLE_GAP}{value:>width$}")?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+}
+
+/// One tab separated `city, sum, count, min, max` line per city, in the
+/// tenths of a degree Celsius they were aggregated in, for `onebrc merge
+/// --raw` to combine. The unit and the columns are ignored, since the merged
+/// results are printed from these totals.
+pub struct RawWriter<W> {
+    out: W,
+}
+
+impl<W: Write> RawWriter<W> {
+    pub fn new(out: W) -> Self {
+        RawWriter { out }
+    }
+}
+
+impl<W: Write> StatsWriter for RawWriter<W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        self.out.write_all(city)?;
+        writeln!(
+            self.out,
+            "\t{}\t{}\t{}\t{}",
+            stats.sum, stats.count, stats.min, stats.max
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Prometheus text format, with a gauge for each statistic and a sample of
+/// it per city, e.g. `temperature_min_celsius{city="Hamburg"} 12.0`. The
+/// samples of a metric have to be grouped, so they are kept until the end.
+pub struct PrometheusWriter<'a, W> {
+    out: W,
+    columns: &'a [Column],
+    unit: Unit,
+    /// The name and samples of every column.
+    metrics: Vec<(String, String)>,
+}
+
+impl<'a, W: Write> PrometheusWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [Column], unit: Unit) -> Self {
+        let names = Self::metric_names(columns, unit);
+        PrometheusWriter {
+            out,
+            columns,
+            unit,
+            metrics: names.map(|name| (name, String::new())).collect(),
+        }
+    }
+
+    fn metric_names(columns: &[Column], unit: Unit) -> impl Iterator<Item = String> + '_ {
+        let unit = match unit {
+            Unit::Celsius => "celsius",
+            Unit::Fahrenheit => "fahrenheit",
+        };
+        columns.iter().map(move |column| match column {
+            Column::Count => "temperature_count".to_string(),
+            column => format!("temperature_{}_{unit}", column.name()),
+        })
+    }
+}
+
+impl<W: Write> StatsWriter for PrometheusWriter<'_, W> {
+    fn write_entry(&mut self, city: &[u8], stats: &Stats) -> io::Result<()> {
+        let label = prometheus_label(&String::from_utf8_lossy(city));
+        let values = self
+            .columns
+            .iter()
+            .map(|column| column.value(stats, self.unit));
+        for ((name, samples), value) in self.metrics.iter_mut().zip(values) {
+            samples.push_str(&format!("{name}{{city=\"{label}\"}} {value}\n"));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for (name, samples) in &self.metrics {
+            if !samples.is_empty() {
+                writeln!(self.out, "# TYPE {name} gauge")?;
+                write!(self.out, "{samples}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `value` as a label value, with backslashes, quotes and line breaks
+/// escaped.
+fn prometheus_label(value: &str) -> String {
+    let mut label = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => label.push_str("\\\\"),
+            '"' => label.push_str("\\\""),
+            '\n' => label.push_str("\\n"),
+            c => label.push(c),
+        }
+    }
+    label
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        args::{Format, SortKey, Unit},
+        output::{
+            ansi_bold, ansi_fg, ansi_reset, exclude_stats, filter_stats, min_count_stats,
+            prometheus_label, sort_stats, stats_writer, top_n, write_entries, write_histograms,
+            write_stats, Column, CsvWriter, DatasetSummary, DefaultWriter, JsonWriter,
+            NdjsonWriter, PrometheusWriter, RawWriter, TableWriter, Tenths, TsvWriter,
+            DEFAULT_COLUMNS, RED,
+        },
+        Extras, Stats,
+    };
+    use pretty_assertions::assert_eq;
+
+    /// Stats of `measures`, in tenths of a degree.
+    fn measured(measures: &[i32]) -> Stats {
+        measured_with(measures, Extras::default())
+    }
+
+    /// [`measured`] with a sample for the median and percentiles.
+    fn sampled(measures: &[i32]) -> Stats {
+        measured_with(
+            measures,
+            Extras {
+                sample: true,
+                ..Extras::default()
+            },
+        )
+    }
+
+    fn measured_with(measures: &[i32], extras: Extras) -> Stats {
+        let mut stats = Stats::new(measures[0], extras);
+        for &measure in &measures[1..] {
+            stats.update(measure);
+        }
+        stats
+    }
+
+    /// Min, avg and max followed by `extra`.
+    fn with_defaults(extra: &[Column]) -> Vec<Column> {
+        [DEFAULT_COLUMNS, extra].concat()
+    }
+
+    #[test]
+    fn it_formats_tenths_like_floats() {
+        for tenths in -1_200..=1_200 {
+            assert_eq!(
+                format!("{:.1}", tenths as f32 / 10.0),
+                Tenths(tenths).to_string()
+            );
+        }
+        assert_eq!("-214748364.8", Tenths(i32::MIN).to_string());
+    }
+
+    fn cities_stats() -> Vec<(&'static [u8], Stats)> {
+        vec![
+            ("Hamburg".as_bytes(), measured(&[120])),
+            ("Istanbul".as_bytes(), measured(&[62, 230])),
+            ("Quote \"\\ City".as_bytes(), measured(&[-5])),
+        ]
+    }
+
+    #[test]
+    fn it_writes_one_decimal_for_round_temperatures() {
+        for (scaled, expected) in [
+            (0, "X=0.0/0.00/0.0"),
+            (100, "X=10.0/10.00/10.0"),
+            (-100, "X=-10.0/-10.00/-10.0"),
+        ] {
+            let stats = measured(&[scaled]);
+            let mut out = vec![];
+            write_stats(&mut out, b"X", &stats, DEFAULT_COLUMNS, Unit::Celsius).unwrap();
+            assert_eq!(expected, String::from_utf8(out).unwrap());
+        }
+    }
+
+    #[test]
+    fn it_converts_to_fahrenheit() {
+        for (celsius, fahrenheit) in [(0, 320), (1000, 2120), (-400, -400), (1, 322), (-5, 311)] {
+            assert_eq!(fahrenheit, Unit::Fahrenheit.tenths(celsius), "{celsius}");
+            assert_eq!(celsius, Unit::Celsius.tenths(celsius));
+        }
+
+        let stats = measured(&[-400, 1000, 1]);
+        let mut out = vec![];
+        write_stats(
+            &mut out,
+            b"X",
+            &stats,
+            &with_defaults(&[Column::Count]),
+            Unit::Fahrenheit,
+        )
+        .unwrap();
+        assert_eq!(
+            "X=-40.0°F/68.06°F/212.0°F/3",
+            String::from_utf8(out).unwrap()
+        );
+        let mut out = vec![];
+        let entries = [(&b"X"[..], stats)];
+        write_entries(
+            &mut CsvWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Fahrenheit),
+            &entries,
+        )
+        .unwrap();
+        assert_eq!(
+            "city,min,avg,max\nX,-40.0,68.06,212.0\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_summarizes_the_dataset() {
+        let summary = DatasetSummary::new(&cities_stats());
+        let mut out = vec![];
+        summary.write(&mut out, Unit::Celsius).unwrap();
+        assert_eq!(
+            "rows         4\n\
+             cities       3\n\
+             min          -0.5\n\
+             max          23.0\n\
+             mean         10.18\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        DatasetSummary::new(&[])
+            .write(&mut out, Unit::Fahrenheit)
+            .unwrap();
+        assert_eq!(
+            "rows         0\ncities       0\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_exact_averages() {
+        for (sum, count, expected) in [
+            (10, 3, "X=0.0/0.33/0.0"),
+            (-1, 2, "X=0.0/-0.05/0.0"),
+            (-1, 3, "X=0.0/-0.03/0.0"),
+            (-1, 30, "X=0.0/0.00/0.0"),
+            // Ties round away from zero, where truncating would give 0.02.
+            (1, 4, "X=0.0/0.03/0.0"),
+            (-1, 4, "X=0.0/-0.03/0.0"),
+            (123_456_789_012, 1_000_000_000, "X=0.0/12.35/0.0"),
+            (-999_000_000_001, 1_000_000_000, "X=0.0/-99.90/0.0"),
+        ] {
+            let stats = Stats::from_raw(sum, count, 0, 0);
+            let mut out = vec![];
+            write_stats(&mut out, b"X", &stats, DEFAULT_COLUMNS, Unit::Celsius).unwrap();
+            assert_eq!(expected, String::from_utf8(out).unwrap());
+        }
+    }
+    #[test]
+    fn it_writes_default() {
+        let mut out = vec![];
+        write_entries(
+            &mut DefaultWriter::new(&mut out, &with_defaults(&[Column::Count]), Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        assert_eq!(
+            "{Hamburg=12.0/12.00/12.0/1, Istanbul=6.2/14.60/23.0/2, Quote \"\\ City=-0.5/-0.50/-0.5/1}\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut DefaultWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &[],
+        )
+        .unwrap();
+        assert_eq!("{}\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn it_writes_colors_for_a_terminal() {
+        let mut out = vec![];
+        let columns = with_defaults(&[Column::Count]);
+        write_entries(
+            &mut DefaultWriter::new(&mut out, &columns, Unit::Fahrenheit).with_color(true),
+            &cities_stats()[..1],
+        )
+        .unwrap();
+        assert_eq!(
+            "{\x1b[1;37mHamburg\x1b[0m=\x1b[36m53.6°F\x1b[0m/\x1b[32m53.60°F\x1b[0m\
+             /\x1b[31m53.6°F\x1b[0m/1}\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut DefaultWriter::one_per_line(&mut out, DEFAULT_COLUMNS, Unit::Celsius)
+                .with_color(false),
+            &cities_stats()[..1],
+        )
+        .unwrap();
+        assert_eq!("Hamburg=12.0/12.00/12.0\n", String::from_utf8(out).unwrap());
+        assert_eq!(
+            ("", "", ""),
+            (ansi_bold(false), &*ansi_fg(false, RED), ansi_reset(false))
+        );
+    }
+
+    #[test]
+    fn it_writes_one_city_per_line() {
+        let mut out = vec![];
+        write_entries(
+            &mut DefaultWriter::one_per_line(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        assert_eq!(
+            "Hamburg=12.0/12.00/12.0\nIstanbul=6.2/14.60/23.0\nQuote \"\\ City=-0.5/-0.50/-0.5\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut DefaultWriter::one_per_line(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &[],
+        )
+        .unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn it_writes_empty_documents() {
+        for (format, expected) in [
+            (Format::Default, "{}\n"),
+            (Format::Json, "[]\n"),
+            (Format::Ndjson, ""),
+            (Format::Csv, "city,min,avg,max,count\n"),
+            (Format::Tsv, ""),
+            (Format::Prometheus, ""),
+            (Format::Raw, ""),
+            (
+                Format::Table,
+                "city  min  avg  max  count\n────  ───  ───  ───  ─────\n",
+            ),
+        ] {
+            let mut out = vec![];
+            write_entries(
+                &mut *stats_writer(
+                    &format,
+                    &mut out,
+                    &with_defaults(&[Column::Count]),
+                    Unit::Celsius,
+                ),
+                &[],
+            )
+            .unwrap();
+            assert_eq!(expected, String::from_utf8(out).unwrap(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn it_writes_json() {
+        let mut out = vec![];
+        write_entries(
+            &mut JsonWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        assert_eq!(
+            concat!(
+                r#"[{"city":"Hamburg","min":12.0,"avg":12.00,"max":12.0},"#,
+                r#"{"city":"Istanbul","min":6.2,"avg":14.60,"max":23.0},"#,
+                r#"{"city":"Quote \"\\ City","min":-0.5,"avg":-0.50,"max":-0.5}]"#,
+                "\n"
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_round_trips_json() {
+        let cities_stats = cities_stats();
+        let mut out = vec![];
+        write_entries(
+            &mut JsonWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+
+        let parsed: Vec<serde_json::Value> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(cities_stats.len(), parsed.len());
+        for ((city, stats), object) in cities_stats.iter().zip(&parsed) {
+            assert_eq!(std::str::from_utf8(city).unwrap(), object["city"]);
+            assert_eq!(stats.min as f64 / 10.0, object["min"].as_f64().unwrap());
+            assert_eq!(stats.max as f64 / 10.0, object["max"].as_f64().unwrap());
+            let avg = stats.sum as f64 / stats.count as f64 / 10.0;
+            assert!((avg - object["avg"].as_f64().unwrap()).abs() < 0.005);
+        }
+    }
+
+    #[test]
+    fn it_writes_csv() {
+        let mut cities_stats = cities_stats();
+        cities_stats.insert(1, ("Hamilton, Ontario".as_bytes(), measured(&[-31, 254])));
+        let mut out = vec![];
+        write_entries(
+            &mut CsvWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        assert_eq!(
+            r#"city,min,avg,max
+Hamburg,12.0,12.00,12.0
+"Hamilton, Ontario",-3.1,11.15,25.4
+Istanbul,6.2,14.60,23.0
+"Quote ""\ City",-0.5,-0.50,-0.5
+"#,
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_prometheus() {
+        let mut out = vec![];
+        write_entries(
+            &mut PrometheusWriter::new(&mut out, &with_defaults(&[Column::Count]), Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        assert_eq!(
+            "# TYPE temperature_min_celsius gauge\n\
+             temperature_min_celsius{city=\"Hamburg\"} 12.0\n\
+             temperature_min_celsius{city=\"Istanbul\"} 6.2\n\
+             temperature_min_celsius{city=\"Quote \\\"\\\\ City\"} -0.5\n\
+             # TYPE temperature_avg_celsius gauge\n\
+             temperature_avg_celsius{city=\"Hamburg\"} 12.00\n\
+             temperature_avg_celsius{city=\"Istanbul\"} 14.60\n\
+             temperature_avg_celsius{city=\"Quote \\\"\\\\ City\"} -0.50\n\
+             # TYPE temperature_max_celsius gauge\n\
+             temperature_max_celsius{city=\"Hamburg\"} 12.0\n\
+             temperature_max_celsius{city=\"Istanbul\"} 23.0\n\
+             temperature_max_celsius{city=\"Quote \\\"\\\\ City\"} -0.5\n\
+             # TYPE temperature_count gauge\n\
+             temperature_count{city=\"Hamburg\"} 1\n\
+             temperature_count{city=\"Istanbul\"} 2\n\
+             temperature_count{city=\"Quote \\\"\\\\ City\"} 1\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!("a\\nb", prometheus_label("a\nb"));
+    }
+
+    #[test]
+    fn it_writes_tsv() {
+        let mut out = vec![];
+        write_entries(
+            &mut TsvWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> = out.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(
+            vec![
+                vec!["Hamburg", "12.0", "12.00", "12.0"],
+                vec!["Istanbul", "6.2", "14.60", "23.0"],
+                vec!["Quote \"\\ City", "-0.5", "-0.50", "-0.5"],
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn it_writes_table() {
+        let cities_stats = [
+            cities_stats(),
+            vec![
+                ("Zürich".as_bytes(), Stats::new(-123, Extras::default())),
+                (
+                    "Petropavlovsk-Kamchatsky".as_bytes(),
+                    Stats::new(5, Extras::default()),
+                ),
+            ],
+        ]
+        .concat();
+        let columns = with_defaults(&[Column::Count]);
+        let mut out = vec![];
+        write_entries(
+            &mut TableWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        assert_eq!(
+            "city                        min     avg    max  count\n\
+             ────────────────────────  ─────  ──────  ─────  ─────\n\
+             Hamburg                    12.0   12.00   12.0      1\n\
+             Istanbul                    6.2   14.60   23.0      2\n\
+             Quote \"\\ City              -0.5   -0.50   -0.5      1\n\
+             Zürich                    -12.3  -12.30  -12.3      1\n\
+             Petropavlovsk-Kamchatsky    0.5    0.50    0.5      1\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_truncates_table_cities_to_the_width() {
+        let cities_stats = [
+            ("Hamburg".as_bytes(), Stats::new(120, Extras::default())),
+            ("Zürich".as_bytes(), Stats::new(-123, Extras::default())),
+        ];
+        let table = |max_width| {
+            let mut out = vec![];
+            write_entries(
+                &mut TableWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius)
+                    .with_max_width(Some(max_width)),
+                &cities_stats,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            "city      min     avg    max\n\
+             ──────  ─────  ──────  ─────\n\
+             Hambu…   12.0   12.00   12.0\n\
+             Zürich  -12.3  -12.30  -12.3\n",
+            table(28)
+        );
+        // Never narrower than the header.
+        assert!(table(0).contains("\nHam…  "));
+        assert!(table(80).contains("\nHamburg  "));
+    }
+
+    #[test]
+    fn it_writes_histograms() {
+        let extras = Extras {
+            histogram: true,
+            ..Extras::default()
+        };
+        let mut istanbul = Stats::new(62, extras);
+        for measure in [230, 150, 199, -5] {
+            istanbul.update(measure);
+        }
+        let cities_stats = [
+            ("Istanbul".as_bytes(), istanbul),
+            ("Oslo".as_bytes(), Stats::new(-999, extras)),
+        ];
+        let mut out = vec![];
+        write_histograms(&mut out, &cities_stats, 24).unwrap();
+        assert_eq!(
+            "Istanbul\n\
+             \x20  -10..0    █████ 1\n\
+             \x20    0..10   █████ 1\n\
+             \x20   10..20   █████████ 2\n\
+             \x20   20..30   █████ 1\n\
+             Oslo\n\
+             \x20 -100..-90  █████████ 1\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_raw() {
+        let mut out = vec![];
+        write_entries(&mut RawWriter::new(&mut out), &cities_stats()).unwrap();
+        assert_eq!(
+            "Hamburg\t120\t1\t120\t120\n\
+             Istanbul\t292\t2\t62\t230\n\
+             Quote \"\\ City\t-5\t1\t-5\t-5\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_ndjson() {
+        let cities_stats = cities_stats();
+        let mut out = vec![];
+        write_entries(
+            &mut NdjsonWriter::new(&mut out, DEFAULT_COLUMNS, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(cities_stats.len(), out.lines().count());
+        for ((city, stats), line) in cities_stats.iter().zip(out.lines()) {
+            let object: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(4, object.as_object().unwrap().len());
+            assert_eq!(std::str::from_utf8(city).unwrap(), object["city"]);
+            assert!(object["min"].is_f64() && object["avg"].is_f64() && object["max"].is_f64());
+            assert_eq!(stats.min as f64 / 10.0, object["min"]);
+            assert_eq!(stats.max as f64 / 10.0, object["max"]);
+        }
+        assert_eq!(
+            r#"{"city":"Istanbul","min":6.2,"avg":14.60,"max":23.0}"#,
+            out.lines().nth(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_median_column() {
+        let stats = sampled(&[62, 230, 100]);
+        let cities_stats = vec![("Istanbul".as_bytes(), stats)];
+        let columns = with_defaults(&[Column::Median]);
+
+        let mut out = vec![];
+        write_stats(
+            &mut out,
+            b"Istanbul",
+            &cities_stats[0].1,
+            &columns,
+            Unit::Celsius,
+        )
+        .unwrap();
+        assert_eq!(
+            "Istanbul=6.2/13.07/23.0/10.0",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut JsonWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        assert_eq!(
+            "[{\"city\":\"Istanbul\",\"min\":6.2,\"avg\":13.07,\"max\":23.0,\"median\":10.0}]\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut CsvWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        assert_eq!(
+            "city,min,avg,max,median\nIstanbul,6.2,13.07,23.0,10.0\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut TsvWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        assert_eq!(
+            "Istanbul\t6.2\t13.07\t23.0\t10.0\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_percentile_columns() {
+        let stats = sampled(&(0..=100).collect::<Vec<_>>());
+        let cities_stats = vec![("Oslo".as_bytes(), stats)];
+        let columns = with_defaults(&[
+            Column::Percentile(50),
+            Column::Percentile(90),
+            Column::Percentile(99),
+        ]);
+
+        let mut out = vec![];
+        write_stats(
+            &mut out,
+            b"Oslo",
+            &cities_stats[0].1,
+            &columns,
+            Unit::Celsius,
+        )
+        .unwrap();
+        assert_eq!(
+            "Oslo=0.0/5.00/10.0/5.0/9.0/9.9",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write_entries(
+            &mut CsvWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats,
+        )
+        .unwrap();
+        assert_eq!(
+            "city,min,avg,max,p50,p90,p99\nOslo,0.0,5.00,10.0,5.0,9.0,9.9\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_selected_columns() {
+        let (city, stats) = &cities_stats()[1];
+        let values = [
+            (Column::Min, "6.2"),
+            (Column::Avg, "14.60"),
+            (Column::Max, "23.0"),
+            (Column::Count, "2"),
+            (Column::Sum, "29.2"),
+        ];
+        // Every non-empty subset, in both orders.
+        for subset in 1..1 << values.len() {
+            let selected: Vec<_> = (0..values.len())
+                .filter(|i| subset & 1 << i != 0)
+                .map(|i| values[i])
+                .collect();
+            for selected in [selected.clone(), selected.into_iter().rev().collect()] {
+                let (columns, expected): (Vec<_>, Vec<_>) = selected.into_iter().unzip();
+                let mut out = vec![];
+                write_stats(&mut out, city, stats, &columns, Unit::Celsius).unwrap();
+                assert_eq!(
+                    format!("Istanbul={}", expected.join("/")),
+                    String::from_utf8(out).unwrap()
+                );
+            }
+        }
+
+        let columns = [Column::Max, Column::Sum];
+        let mut out = vec![];
+        write_entries(
+            &mut CsvWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        assert_eq!(
+            "city,max,sum\nHamburg,12.0,12.0\nIstanbul,23.0,29.2\n\"Quote \"\"\\ City\",-0.5,-0.5\n",
+            String::from_utf8(out).unwrap()
+        );
+        let mut out = vec![];
+        write_entries(
+            &mut JsonWriter::new(&mut out, &columns, Unit::Celsius),
+            &cities_stats()[..1],
+        )
+        .unwrap();
+        assert_eq!(
+            "[{\"city\":\"Hamburg\",\"max\":12.0,\"sum\":12.0}]\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_sums() {
+        for (sum, count, unit, expected) in [
+            (292, 2, Unit::Celsius, "X=29.2"),
+            (-5, 1, Unit::Celsius, "X=-0.5"),
+            (
+                -30_000_000_000,
+                300_000_000,
+                Unit::Celsius,
+                "X=-3000000000.0",
+            ),
+            (292, 2, Unit::Fahrenheit, "X=116.6°F"),
+            (-5, 1, Unit::Fahrenheit, "X=31.1°F"),
+            // -0.04°F rounds to zero, without a sign.
+            (-178, 1, Unit::Fahrenheit, "X=0.0°F"),
+        ] {
+            let stats = Stats::from_raw(sum, count, 0, 0);
+            let mut out = vec![];
+            write_stats(&mut out, b"X", &stats, &[Column::Sum], unit).unwrap();
+            assert_eq!(expected, String::from_utf8(out).unwrap());
+        }
+    }
+
+    #[test]
+    fn it_writes_stddev_column() {
+        let mut out = vec![];
+        write_entries(
+            &mut TsvWriter::new(&mut out, &with_defaults(&[Column::Stddev]), Unit::Celsius),
+            &cities_stats(),
+        )
+        .unwrap();
+        assert_eq!(
+            "Hamburg\t12.0\t12.00\t12.0\t0.00\n\
+             Istanbul\t6.2\t14.60\t23.0\t8.40\n\
+             Quote \"\\ City\t-0.5\t-0.50\t-0.5\t0.00\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_sorts_stats() {
+        let sorted = |key| {
+            let mut entries = cities_stats();
+            entries.reverse();
+            sort_stats(&mut entries, key);
+            entries
+                .into_iter()
+                .map(|(city, _)| std::str::from_utf8(city).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let hamburg = "Hamburg";
+        let istanbul = "Istanbul";
+        let quote = "Quote \"\\ City";
+        assert_eq!(vec![hamburg, istanbul, quote], sorted(SortKey::Name));
+        assert_eq!(vec![hamburg, istanbul, quote], sorted(SortKey::Min));
+        assert_eq!(vec![istanbul, hamburg, quote], sorted(SortKey::Max));
+        assert_eq!(vec![istanbul, hamburg, quote], sorted(SortKey::Avg));
+        // Hamburg and the quoted city both have one measurement.
+        assert_eq!(vec![istanbul, hamburg, quote], sorted(SortKey::Count));
+    }
+
+    #[test]
+    fn it_keeps_top_n_by_max() {
+        let top = |n| {
+            let mut entries = cities_stats();
+            top_n(&mut entries, n);
+            entries
+                .into_iter()
+                .map(|(city, _)| std::str::from_utf8(city).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["Istanbul"], top(1));
+        assert_eq!(vec!["Istanbul", "Hamburg"], top(2));
+        assert_eq!(vec!["Istanbul", "Hamburg", "Quote \"\\ City"], top(3));
+        assert_eq!(vec!["Istanbul", "Hamburg", "Quote \"\\ City"], top(10));
+    }
+
+    #[test]
+    fn it_filters_cities_ignoring_case() {
+        let filtered = |pattern| {
+            let mut entries = cities_stats();
+            entries.push(("Islamabad".as_bytes(), measured(&[150])));
+            filter_stats(&mut entries, pattern);
+            entries
+                .into_iter()
+                .map(|(city, _)| std::str::from_utf8(city).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["Istanbul", "Islamabad"], filtered("Is"));
+        assert_eq!(vec!["Istanbul", "Islamabad"], filtered("iS"));
+        assert_eq!(Vec::<&str>::new(), filtered("Paris"));
+        assert_eq!(4, filtered("").len());
+    }
+
+    #[test]
+    fn it_excludes_after_filtering() {
+        let mut entries: Vec<(&[u8], Stats)> = ["Abu Dhabi", "Accra", "Abéché", "Bamako", "Dakar"]
+            .into_iter()
+            .map(|city| (city.as_bytes(), measured(&[0])))
+            .collect();
+        filter_stats(&mut entries, "A");
+        exclude_stats(&mut entries, "AB");
+        let cities: Vec<&str> = entries
+            .into_iter()
+            .map(|(city, _)| std::str::from_utf8(city).unwrap())
+            .collect();
+        assert_eq!(vec!["Accra", "Bamako", "Dakar"], cities);
+    }
+
+    #[test]
+    fn it_drops_cities_with_few_measurements() {
+        let mut entries = cities_stats();
+        min_count_stats(&mut entries, 2);
+        assert_eq!(
+            vec![&b"Istanbul"[..]],
+            entries.iter().map(|(city, _)| *city).collect::<Vec<_>>()
+        );
+        min_count_stats(&mut entries, 3);
+        assert!(entries.is_empty());
+    }
+}
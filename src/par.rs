@@ -0,0 +1,56 @@
+use crate::{
+    chunks, merge_maps, process_chunk, table::CityTable, timer::PhaseTimer, AggregateError, Extras,
+    ParseOptions, Stats,
+};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use std::{cmp, hash::BuildHasher, io};
+
+/// Aggregates `buffer` with a rayon pipeline on a pool of `num_threads`:
+/// every rayon job folds its chunks into one table, and the tables are
+/// reduced pairwise into the final map.
+pub fn multi_thread<'a, S: BuildHasher + Clone + Send + Sync>(
+    buffer: &'a [u8],
+    num_threads: usize,
+    chunk_size: usize,
+    extras: Extras,
+    options: ParseOptions,
+    hasher: S,
+    timer: &mut PhaseTimer,
+) -> Result<Vec<(&'a [u8], Stats)>, AggregateError> {
+    let chunks = chunks(buffer, chunk_size);
+    timer.phase("chunks");
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .map_err(|err| AggregateError::Spawn(io::Error::other(err)))?;
+    let cities_stats = pool.install(|| {
+        chunks
+            .par_iter()
+            .zip(options.for_chunks(buffer, &chunks))
+            .fold(
+                || Ok(CityTable::with_hasher(hasher.clone())),
+                |table, (chunk, options)| {
+                    // A fold sees its chunks in order, so the ones after a
+                    // malformed row can be skipped.
+                    let mut table = table?;
+                    process_chunk(chunk, &mut table, extras, options)
+                        .map(|()| table)
+                        .map_err(|err| err.within(buffer, chunk))
+                },
+            )
+            .map(|table| table.map(|table| table.into_entries().collect()))
+            // Not `try_reduce`, which would stop at whichever error comes
+            // back first rather than the one earliest in the buffer.
+            .reduce(
+                || Ok(FxHashMap::default()),
+                |left, right| match (left, right) {
+                    (Ok(left), Ok(right)) => Ok(merge_maps(left, right)),
+                    (Err(left), Err(right)) => Err(cmp::min_by_key(left, right, |err| err.offset)),
+                    (Err(err), Ok(_)) | (Ok(_), Err(err)) => Err(err),
+                },
+            )
+    });
+    timer.phase("workers");
+    Ok(cities_stats?.into_iter().collect())
+}
@@ -0,0 +1,168 @@
+use crate::{
+    chunks, interner::Interner, process_chunk, table::CityTable, timer::PhaseTimer, AggregateError,
+    Extras, ParseOptions, RowError, Stats,
+};
+use std::{
+    hash::BuildHasher,
+    panic,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// The stats of a worker, indexed by the interned id of their city.
+type Table = Vec<Option<Stats>>;
+
+/// Aggregates `buffer` on a pool of `num_threads` scoped workers, which
+/// claim chunks from a shared counter until none are left.
+///
+/// The workers also merge their tables pairwise: a finished worker takes
+/// the table parked by one that finished earlier, merges it into its own,
+/// and parks the result once there is none left to take. Workers that
+/// finish together thus merge a tree of tables in parallel, instead of
+/// one thread folding in every table in turn.
+pub fn multi_thread<'a, S: BuildHasher + Clone + Send>(
+    buffer: &'a [u8],
+    num_threads: usize,
+    chunk_size: usize,
+    extras: Extras,
+    options: ParseOptions,
+    hasher: S,
+    timer: &mut PhaseTimer,
+) -> Result<Vec<(&'a [u8], Stats)>, AggregateError> {
+    let chunks = chunks(buffer, chunk_size);
+    let chunks: Vec<_> = chunks
+        .iter()
+        .copied()
+        .zip(options.for_chunks(buffer, &chunks))
+        .collect();
+    timer.phase("chunks");
+    let next_chunk = AtomicUsize::new(0);
+    let interner = Mutex::new(Interner::default());
+    let parked: Mutex<Option<Table>> = Mutex::new(None);
+    let num_workers = num_threads.min(chunks.len());
+
+    // Scoped threads may borrow `buffer`, and the compiler checks that none
+    // of them outlives it.
+    thread::scope(|scope| {
+        let mut handles = Vec::with_capacity(num_workers);
+        for _ in 0..num_workers {
+            let (chunks, next_chunk, interner, parked) = (&chunks, &next_chunk, &interner, &parked);
+            let hasher = hasher.clone();
+            let worker = thread::Builder::new().spawn_scoped(scope, move || {
+                let mut cities_stats = CityTable::with_hasher(hasher);
+                while let Some(&(chunk, options)) =
+                    chunks.get(next_chunk.fetch_add(1, Ordering::Relaxed))
+                {
+                    process_chunk(chunk, &mut cities_stats, extras, options)
+                        .map_err(|err| err.within(buffer, chunk))?;
+                }
+                // One lock per worker: every name is interned only after the
+                // whole share of the file has been aggregated.
+                let entries: Vec<_> = {
+                    let mut interner = interner.lock().unwrap();
+                    cities_stats
+                        .into_entries()
+                        .map(|(city, stats)| (interner.intern(city) as usize, stats))
+                        .collect()
+                };
+                let mut table = Table::new();
+                for (id, stats) in entries {
+                    if table.len() <= id {
+                        table.resize_with(id + 1, || None);
+                    }
+                    table[id] = Some(stats);
+                }
+                loop {
+                    let mut slot = parked.lock().unwrap();
+                    match slot.take() {
+                        Some(other) => {
+                            drop(slot);
+                            merge_tables(&mut table, other);
+                        }
+                        None => {
+                            *slot = Some(table);
+                            return Ok::<_, RowError>(());
+                        }
+                    }
+                }
+            });
+            handles.push(worker.map_err(AggregateError::Spawn)?);
+        }
+        // Chunks are claimed in order, so whichever worker met the first
+        // malformed row has reported it; the others may be further on.
+        let mut errors = vec![];
+        for handle in handles {
+            match handle.join() {
+                Ok(result) => errors.extend(result.err()),
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+        match errors.into_iter().min_by_key(|err| err.offset) {
+            Some(err) => Err(AggregateError::Row(err)),
+            None => Ok(()),
+        }
+    })?;
+    timer.phase("workers");
+
+    // Every worker parks its table last, so only one is left.
+    let merged = parked.into_inner().unwrap().unwrap_or_default();
+    let names = interner.into_inner().unwrap().into_names();
+    let cities_stats = names
+        .into_iter()
+        .zip(merged)
+        .filter_map(|(name, stats)| Some((name, stats?)))
+        .collect();
+    timer.phase("merge");
+
+    Ok(cities_stats)
+}
+
+/// Folds `from` into `into`, city by city.
+fn merge_tables(into: &mut Table, from: Table) {
+    if into.len() < from.len() {
+        into.resize_with(from.len(), || None);
+    }
+    for (into, from) in into.iter_mut().zip(from) {
+        match (into, from) {
+            (Some(into), Some(from)) => into.merge(from),
+            (into @ None, from) => *into = from,
+            (Some(_), None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{pool::multi_thread, timer::PhaseTimer, Extras, ParseOptions};
+    use pretty_assertions::assert_eq;
+    use rustc_hash::FxHasher;
+    use std::{collections::BTreeMap, hash::BuildHasherDefault};
+
+    #[test]
+    fn it_merges_the_tables_of_many_workers() {
+        let content: String = (0..20_000)
+            .map(|i| format!("Town {};{}.{}\n", i % 500, i % 199 - 99, i % 10))
+            .collect();
+        let aggregate = |num_threads, chunk_size| {
+            multi_thread(
+                content.as_bytes(),
+                num_threads,
+                chunk_size,
+                Extras::default(),
+                ParseOptions::default(),
+                BuildHasherDefault::<FxHasher>::default(),
+                &mut PhaseTimer::new(),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|(city, stats)| (city, (stats.min, stats.max, stats.sum, stats.count)))
+            .collect::<BTreeMap<_, _>>()
+        };
+        let expected = aggregate(1, content.len());
+        assert_eq!(500, expected.len());
+        assert_eq!(expected, aggregate(64, 1024));
+    }
+}
@@ -0,0 +1,175 @@
+/// Maximum number of measurements kept per city.
+pub const RESERVOIR_SIZE: usize = 1024;
+
+/// A uniform random sample of at most [`RESERVOIR_SIZE`] measurements,
+/// maintained with Algorithm R.
+///
+/// Quantiles computed from the sample are estimates: the standard error of
+/// the sample median is about `1.25 * σ / sqrt(1024) ≈ 0.04 σ`, i.e. roughly
+/// 2.3°C for temperatures uniformly spread over [-99.9, 99.9].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reservoir {
+    samples: Vec<i16>,
+    /// Measurements offered to the reservoir so far.
+    seen: u64,
+    rng: u64,
+}
+
+impl Default for Reservoir {
+    fn default() -> Self {
+        Reservoir {
+            samples: Vec::with_capacity(RESERVOIR_SIZE),
+            seen: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+impl Reservoir {
+    #[inline(always)]
+    pub fn push(&mut self, measurement: i16) {
+        self.seen += 1;
+        if self.samples.len() < RESERVOIR_SIZE {
+            self.samples.push(measurement);
+        } else {
+            let j = self.next_below(self.seen);
+            if j < RESERVOIR_SIZE as u64 {
+                self.samples[j as usize] = measurement;
+            }
+        }
+    }
+
+    /// Combines two samples of disjoint inputs into a sample of their union:
+    /// each slot is drawn from `self` or `other` in proportion to how many
+    /// measurements each of them has seen.
+    pub fn merge(&mut self, mut other: Reservoir) {
+        let seen = self.seen + other.seen;
+        if self.samples.len() + other.samples.len() <= RESERVOIR_SIZE {
+            self.samples.append(&mut other.samples);
+            self.seen = seen;
+            return;
+        }
+
+        let mut ours = std::mem::take(&mut self.samples);
+        let (mut ours_left, mut theirs_left) = (self.seen, other.seen);
+        let mut merged = Vec::with_capacity(RESERVOIR_SIZE);
+        while merged.len() < RESERVOIR_SIZE {
+            let from_ours = other.samples.is_empty()
+                || (!ours.is_empty() && self.next_below(ours_left + theirs_left) < ours_left);
+            let source = if from_ours {
+                ours_left -= 1;
+                &mut ours
+            } else {
+                theirs_left -= 1;
+                &mut other.samples
+            };
+            let j = self.next_below(source.len() as u64) as usize;
+            merged.push(source.swap_remove(j));
+        }
+        self.samples = merged;
+        self.seen = seen;
+    }
+
+    /// Estimates the `q` quantile (0.0..=1.0) of the scaled measurements,
+    /// linearly interpolating between the two closest samples.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let rank = q * (sorted.len() - 1) as f64;
+        let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+        let weight = rank - lo as f64;
+        sorted[lo] as f64 * (1.0 - weight) + sorted[hi] as f64 * weight
+    }
+
+    pub fn median(&self) -> f64 {
+        self.quantile(0.5)
+    }
+
+    /// Uniform integer in `0..n` from a xorshift64* generator. The modulo
+    /// bias is negligible for `n` far below 2^64.
+    #[inline(always)]
+    fn next_below(&mut self, n: u64) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) % n
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::reservoir::{Reservoir, RESERVOIR_SIZE};
+    use pretty_assertions::assert_eq;
+
+    /// 10000 measurements spread uniformly over [-99.9, 99.9], shuffled.
+    fn uniform() -> Vec<i16> {
+        let mut values: Vec<i16> = (0..10_000)
+            .map(|i| -999 + (i * 1999 / 10_000) as i16)
+            .collect();
+        let mut state: u64 = 42;
+        for i in (1..values.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            values.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        values
+    }
+
+    #[test]
+    fn it_keeps_every_measurement_below_capacity() {
+        let mut reservoir = Reservoir::default();
+        for measurement in [30, 10, 20] {
+            reservoir.push(measurement);
+        }
+        assert_eq!(20.0, reservoir.median());
+        assert_eq!(10.0, reservoir.quantile(0.0));
+        assert_eq!(25.0, reservoir.quantile(0.75));
+    }
+
+    #[test]
+    fn it_estimates_the_median_of_uniform_values() {
+        let mut reservoir = Reservoir::default();
+        for measurement in uniform() {
+            reservoir.push(measurement);
+        }
+        assert_eq!(RESERVOIR_SIZE, reservoir.samples.len());
+        // The true median is 0; allow 5% of the [-999, 999] range.
+        assert!(reservoir.median().abs() < 0.05 * 1998.0);
+    }
+
+    #[test]
+    fn it_estimates_percentiles_of_uniform_values() {
+        let mut reservoir = Reservoir::default();
+        for measurement in uniform() {
+            reservoir.push(measurement);
+        }
+        // The true percentile p is -999 + 1998 * p / 100; allow 5% of the
+        // range, like the median.
+        for p in [1, 10, 25, 50, 75, 90, 99] {
+            let expected = -999.0 + 1998.0 * p as f64 / 100.0;
+            let estimate = reservoir.quantile(p as f64 / 100.0);
+            assert!(
+                (estimate - expected).abs() < 0.05 * 1998.0,
+                "p{p}: {estimate}"
+            );
+        }
+        assert!(reservoir.quantile(0.0) <= reservoir.quantile(0.01));
+        assert!(reservoir.quantile(0.99) <= reservoir.quantile(1.0));
+    }
+
+    #[test]
+    fn it_merges_reservoirs() {
+        let values = uniform();
+        let mut left = Reservoir::default();
+        let mut right = Reservoir::default();
+        for &measurement in &values[..3_000] {
+            left.push(measurement);
+        }
+        for &measurement in &values[3_000..] {
+            right.push(measurement);
+        }
+        left.merge(right);
+        assert_eq!(10_000, left.seen);
+        assert_eq!(RESERVOIR_SIZE, left.samples.len());
+        assert!(left.median().abs() < 0.05 * 1998.0);
+    }
+}
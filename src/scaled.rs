@@ -0,0 +1,91 @@
+//! Serde helpers that write fixed-point fields as the floating-point values
+//! they stand for, e.g. a `min` of `-123` tenths as `-12.3`.
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+macro_rules! scale {
+    ($name:ident, $scale:literal) => {
+        pub mod $name {
+            use serde::{Deserializer, Serializer};
+
+            pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                T: Copy + Into<i64>,
+                S: Serializer,
+            {
+                super::serialize::<T, S, $scale>(value, serializer)
+            }
+
+            pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+            where
+                T: TryFrom<i64>,
+                D: Deserializer<'de>,
+            {
+                super::deserialize::<T, D, $scale>(deserializer)
+            }
+        }
+    };
+}
+
+scale!(tenths, 10);
+scale!(hundredths, 100);
+
+/// Serializes `value`, in units of `1 / SCALE`, as a float.
+fn serialize<T, S, const SCALE: u32>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Copy + Into<i64>,
+    S: Serializer,
+{
+    serializer.serialize_f64((*value).into() as f64 / SCALE as f64)
+}
+
+/// Reads a float back into units of `1 / SCALE`, rounding to the nearest.
+fn deserialize<'de, T, D, const SCALE: u32>(deserializer: D) -> Result<T, D::Error>
+where
+    T: TryFrom<i64>,
+    D: Deserializer<'de>,
+{
+    let value = f64::deserialize(deserializer)?;
+    let scaled = (value * SCALE as f64).round();
+    T::try_from(scaled as i64)
+        .map_err(|_| serde::de::Error::custom(format!("{value} is out of range")))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Extras, Stats};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_round_trips_stats() {
+        let mut stats = Stats::new(
+            -123,
+            Extras {
+                sample: true,
+                ..Extras::default()
+            },
+        );
+        stats.update(999);
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "min": -12.3,
+                "max": 99.9,
+                "sum": 87.6,
+                "sum_sq": 10131.3,
+                "count": 2,
+            }),
+            json
+        );
+
+        let round_tripped: Stats = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            Stats {
+                reservoir: None,
+                histogram: None,
+                ..stats
+            },
+            round_tripped
+        );
+    }
+}
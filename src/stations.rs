@@ -0,0 +1,506 @@
+//! The weather stations of the official 1BRC measurement generator, with a
+//! compile-time perfect hash from their names to indices into [`STATIONS`].
+
+/// Station names in the order of the generator's source.
+pub const STATIONS: [&str; 413] = [
+    "Abha",
+    "Abidjan",
+    "Abéché",
+    "Accra",
+    "Addis Ababa",
+    "Adelaide",
+    "Aden",
+    "Ahvaz",
+    "Albuquerque",
+    "Alexandra",
+    "Alexandria",
+    "Algiers",
+    "Alice Springs",
+    "Almaty",
+    "Amsterdam",
+    "Anadyr",
+    "Anchorage",
+    "Andorra la Vella",
+    "Ankara",
+    "Antananarivo",
+    "Antsiranana",
+    "Arkhangelsk",
+    "Ashgabat",
+    "Asmara",
+    "Assab",
+    "Astana",
+    "Athens",
+    "Atlanta",
+    "Auckland",
+    "Austin",
+    "Baghdad",
+    "Baguio",
+    "Baku",
+    "Baltimore",
+    "Bamako",
+    "Bangkok",
+    "Bangui",
+    "Banjul",
+    "Barcelona",
+    "Bata",
+    "Batumi",
+    "Beijing",
+    "Beirut",
+    "Belgrade",
+    "Belize City",
+    "Benghazi",
+    "Bergen",
+    "Berlin",
+    "Bilbao",
+    "Birao",
+    "Bishkek",
+    "Bissau",
+    "Blantyre",
+    "Bloemfontein",
+    "Boise",
+    "Bordeaux",
+    "Bosaso",
+    "Boston",
+    "Bouaké",
+    "Bratislava",
+    "Brazzaville",
+    "Bridgetown",
+    "Brisbane",
+    "Brussels",
+    "Bucharest",
+    "Budapest",
+    "Bujumbura",
+    "Bulawayo",
+    "Burnie",
+    "Busan",
+    "Cabo San Lucas",
+    "Cairns",
+    "Cairo",
+    "Calgary",
+    "Canberra",
+    "Cape Town",
+    "Changsha",
+    "Charlotte",
+    "Chiang Mai",
+    "Chicago",
+    "Chihuahua",
+    "Chișinău",
+    "Chittagong",
+    "Chongqing",
+    "Christchurch",
+    "City of San Marino",
+    "Colombo",
+    "Columbus",
+    "Conakry",
+    "Copenhagen",
+    "Cotonou",
+    "Cracow",
+    "Da Lat",
+    "Da Nang",
+    "Dakar",
+    "Dallas",
+    "Damascus",
+    "Dampier",
+    "Dar es Salaam",
+    "Darwin",
+    "Denpasar",
+    "Denver",
+    "Detroit",
+    "Dhaka",
+    "Dikson",
+    "Dili",
+    "Djibouti",
+    "Dodoma",
+    "Dolisie",
+    "Douala",
+    "Dubai",
+    "Dublin",
+    "Dunedin",
+    "Durban",
+    "Dushanbe",
+    "Edinburgh",
+    "Edmonton",
+    "El Paso",
+    "Entebbe",
+    "Erbil",
+    "Erzurum",
+    "Fairbanks",
+    "Fianarantsoa",
+    "Flores,  Petén",
+    "Frankfurt",
+    "Fresno",
+    "Fukuoka",
+    "Gabès",
+    "Gaborone",
+    "Gagnoa",
+    "Gangtok",
+    "Garissa",
+    "Garoua",
+    "George Town",
+    "Ghanzi",
+    "Gjoa Haven",
+    "Guadalajara",
+    "Guangzhou",
+    "Guatemala City",
+    "Halifax",
+    "Hamburg",
+    "Hamilton",
+    "Hanga Roa",
+    "Hanoi",
+    "Harare",
+    "Harbin",
+    "Hargeisa",
+    "Hat Yai",
+    "Havana",
+    "Helsinki",
+    "Heraklion",
+    "Hiroshima",
+    "Ho Chi Minh City",
+    "Hobart",
+    "Hong Kong",
+    "Honiara",
+    "Honolulu",
+    "Houston",
+    "Ifrane",
+    "Indianapolis",
+    "Iqaluit",
+    "Irkutsk",
+    "Istanbul",
+    "İzmir",
+    "Jacksonville",
+    "Jakarta",
+    "Jayapura",
+    "Jerusalem",
+    "Johannesburg",
+    "Jos",
+    "Juba",
+    "Kabul",
+    "Kampala",
+    "Kandi",
+    "Kankan",
+    "Kano",
+    "Kansas City",
+    "Karachi",
+    "Karonga",
+    "Kathmandu",
+    "Khartoum",
+    "Kingston",
+    "Kinshasa",
+    "Kolkata",
+    "Kuala Lumpur",
+    "Kumasi",
+    "Kunming",
+    "Kuopio",
+    "Kuwait City",
+    "Kyiv",
+    "Kyoto",
+    "La Ceiba",
+    "La Paz",
+    "Lagos",
+    "Lahore",
+    "Lake Havasu City",
+    "Lake Tekapo",
+    "Las Palmas de Gran Canaria",
+    "Las Vegas",
+    "Launceston",
+    "Lhasa",
+    "Libreville",
+    "Lisbon",
+    "Livingstone",
+    "Ljubljana",
+    "Lodwar",
+    "Lomé",
+    "London",
+    "Los Angeles",
+    "Louisville",
+    "Luanda",
+    "Lubumbashi",
+    "Lusaka",
+    "Luxembourg City",
+    "Lviv",
+    "Lyon",
+    "Madrid",
+    "Mahajanga",
+    "Makassar",
+    "Makurdi",
+    "Malabo",
+    "Malé",
+    "Managua",
+    "Manama",
+    "Mandalay",
+    "Mango",
+    "Manila",
+    "Maputo",
+    "Marrakesh",
+    "Marseille",
+    "Maun",
+    "Medan",
+    "Mek'ele",
+    "Melbourne",
+    "Memphis",
+    "Mexicali",
+    "Mexico City",
+    "Miami",
+    "Milan",
+    "Milwaukee",
+    "Minneapolis",
+    "Minsk",
+    "Mogadishu",
+    "Mombasa",
+    "Monaco",
+    "Moncton",
+    "Monterrey",
+    "Montreal",
+    "Moscow",
+    "Mumbai",
+    "Murmansk",
+    "Muscat",
+    "Mzuzu",
+    "N'Djamena",
+    "Naha",
+    "Nairobi",
+    "Nakhon Ratchasima",
+    "Napier",
+    "Napoli",
+    "Nashville",
+    "Nassau",
+    "Ndola",
+    "New Delhi",
+    "New Orleans",
+    "New York City",
+    "Ngaoundéré",
+    "Niamey",
+    "Nicosia",
+    "Niigata",
+    "Nouadhibou",
+    "Nouakchott",
+    "Novosibirsk",
+    "Nuuk",
+    "Odesa",
+    "Odienné",
+    "Oklahoma City",
+    "Omaha",
+    "Oranjestad",
+    "Oslo",
+    "Ottawa",
+    "Ouagadougou",
+    "Ouahigouya",
+    "Ouarzazate",
+    "Oulu",
+    "Palembang",
+    "Palermo",
+    "Palm Springs",
+    "Palmerston North",
+    "Panama City",
+    "Parakou",
+    "Paris",
+    "Perth",
+    "Petropavlovsk-Kamchatsky",
+    "Philadelphia",
+    "Phnom Penh",
+    "Phoenix",
+    "Pittsburgh",
+    "Podgorica",
+    "Pointe-Noire",
+    "Pontianak",
+    "Port Moresby",
+    "Port Sudan",
+    "Port Vila",
+    "Port-Gentil",
+    "Portland (OR)",
+    "Porto",
+    "Prague",
+    "Praia",
+    "Pretoria",
+    "Pyongyang",
+    "Rabat",
+    "Rangpur",
+    "Reggane",
+    "Reykjavík",
+    "Riga",
+    "Riyadh",
+    "Rome",
+    "Roseau",
+    "Rostov-on-Don",
+    "Sacramento",
+    "Saint Petersburg",
+    "Saint-Pierre",
+    "Salt Lake City",
+    "San Antonio",
+    "San Diego",
+    "San Francisco",
+    "San Jose",
+    "San José",
+    "San Juan",
+    "San Salvador",
+    "Sana'a",
+    "Santo Domingo",
+    "Sapporo",
+    "Sarajevo",
+    "Saskatoon",
+    "Seattle",
+    "Ségou",
+    "Seoul",
+    "Seville",
+    "Shanghai",
+    "Singapore",
+    "Skopje",
+    "Sochi",
+    "Sofia",
+    "Sokoto",
+    "Split",
+    "St. John's",
+    "St. Louis",
+    "Stockholm",
+    "Surabaya",
+    "Suva",
+    "Suwałki",
+    "Sydney",
+    "Tabora",
+    "Tabriz",
+    "Taipei",
+    "Tallinn",
+    "Tamale",
+    "Tamanrasset",
+    "Tampa",
+    "Tashkent",
+    "Tauranga",
+    "Tbilisi",
+    "Tegucigalpa",
+    "Tehran",
+    "Tel Aviv",
+    "Thessaloniki",
+    "Thiès",
+    "Tijuana",
+    "Timbuktu",
+    "Tirana",
+    "Toamasina",
+    "Tokyo",
+    "Toliara",
+    "Toluca",
+    "Toronto",
+    "Tripoli",
+    "Tromsø",
+    "Tucson",
+    "Tunis",
+    "Ulaanbaatar",
+    "Upington",
+    "Ürümqi",
+    "Vaduz",
+    "Valencia",
+    "Valletta",
+    "Vancouver",
+    "Veracruz",
+    "Vienna",
+    "Vientiane",
+    "Villahermosa",
+    "Vilnius",
+    "Virginia Beach",
+    "Vladivostok",
+    "Warsaw",
+    "Washington, D.C.",
+    "Wau",
+    "Wellington",
+    "Whitehorse",
+    "Wichita",
+    "Willemstad",
+    "Winnipeg",
+    "Wrocław",
+    "Xi'an",
+    "Yakutsk",
+    "Yangon",
+    "Yaoundé",
+    "Yellowknife",
+    "Yerevan",
+    "Yinchuan",
+    "Zagreb",
+    "Zanzibar City",
+    "Zürich",
+];
+
+/// Multiplier found offline so that [`slot`] is collision-free over
+/// [`STATIONS`]; building [`SLOTS`] fails to compile if it stops being so.
+const SEED: u64 = 0x5762_6c3e_1cd1_b6a5;
+/// 8192 two-byte slots: 16 KiB, small enough to stay in L1.
+const SLOT_BITS: u32 = 13;
+const EMPTY: u16 = u16::MAX;
+
+/// Station index for each slot, or [`EMPTY`].
+static SLOTS: [u16; 1 << SLOT_BITS] = build_slots();
+
+const fn build_slots() -> [u16; 1 << SLOT_BITS] {
+    let mut slots = [EMPTY; 1 << SLOT_BITS];
+    let mut i = 0;
+    while i < STATIONS.len() {
+        let slot = slot(STATIONS[i].as_bytes());
+        assert!(slots[slot] == EMPTY, "SEED does not separate STATIONS");
+        slots[slot] = i as u16;
+        i += 1;
+    }
+    slots
+}
+
+/// Index of `city` in [`STATIONS`], if it is one of them.
+#[inline(always)]
+pub fn index(city: &[u8]) -> Option<usize> {
+    let i = SLOTS[slot(city)] as usize;
+    STATIONS
+        .get(i)
+        .filter(|station| station.as_bytes() == city)
+        .map(|_| i)
+}
+
+/// Hashes the first and last eight bytes and the length, which already tell
+/// all stations apart, so long names cost no more than short ones.
+#[inline(always)]
+const fn slot(city: &[u8]) -> usize {
+    let len = city.len();
+    let first = word(city, 0);
+    let last = if len > 8 { word(city, len - 8) } else { 0 };
+    let key = first ^ last.rotate_left(29) ^ len as u64;
+    (key.wrapping_mul(SEED) >> (u64::BITS - SLOT_BITS)) as usize
+}
+
+/// Up to eight bytes of `city` from `at`, little-endian and zero padded.
+#[inline(always)]
+const fn word(city: &[u8], at: usize) -> u64 {
+    if city.len() >= at + 8 {
+        return u64::from_le_bytes([
+            city[at],
+            city[at + 1],
+            city[at + 2],
+            city[at + 3],
+            city[at + 4],
+            city[at + 5],
+            city[at + 6],
+            city[at + 7],
+        ]);
+    }
+    let mut word = 0;
+    let mut i = at;
+    while i < city.len() {
+        word |= (city[i] as u64) << (8 * (i - at));
+        i += 1;
+    }
+    word
+}
+
+#[cfg(test)]
+mod test {
+    use crate::stations::{index, STATIONS};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_maps_every_station_to_its_own_index() {
+        for (i, station) in STATIONS.iter().enumerate() {
+            assert_eq!(Some(i), index(station.as_bytes()), "{station}");
+        }
+    }
+
+    #[test]
+    fn it_rejects_unknown_cities() {
+        for city in ["", "Ham", "Hamburgo", "San Jos", "Quote \"\\ City", "C123"] {
+            assert_eq!(None, index(city.as_bytes()), "{city}");
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{stations, Stats};
+use rustc_hash::FxHasher;
+use std::hash::{BuildHasher, BuildHasherDefault};
+
+/// Initial number of slots. At the half-full growth threshold this holds
+/// 1024 cities, so the 413-city standard dataset never has to grow.
+pub const INITIAL_SLOTS: usize = 2048;
+
+/// A flat open-addressing map from city names to their stats.
+///
+/// Entries live inline in a single power-of-two sized `Vec` and collisions
+/// are resolved by quadratic (triangular) probing, which visits every slot
+/// of such a table. The table doubles when it gets half full, so probe
+/// sequences stay short.
+///
+/// The stations of the official dataset bypass hashing altogether: they are
+/// found through [`stations::index`] and kept in a dense array.
+pub struct CityTable<'a, S = BuildHasherDefault<FxHasher>> {
+    stations: Vec<Option<Stats>>,
+    /// The city is kept as a slice rather than as an offset and length into
+    /// the input: files of a billion rows are past the 4 GiB a `u32` offset
+    /// can address, and `None` takes no room of its own, being a null city.
+    /// A slot is 64 bytes, so none straddles two cache lines.
+    slots: Vec<Option<(&'a [u8], Stats)>>,
+    len: usize,
+    hasher: S,
+}
+
+impl<'a> Default for CityTable<'a> {
+    fn default() -> Self {
+        CityTable::with_hasher(BuildHasherDefault::default())
+    }
+}
+
+impl<'a, S: BuildHasher> CityTable<'a, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        CityTable {
+            stations: (0..stations::STATIONS.len()).map(|_| None).collect(),
+            slots: (0..INITIAL_SLOTS).map(|_| None).collect(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    /// Applies `update` to the stats of `city`, or inserts `init()` if it is
+    /// new, so a first measurement can initialize its stats directly.
+    #[inline(always)]
+    pub fn update_or_insert_with(
+        &mut self,
+        city: &'a [u8],
+        update: impl FnOnce(&mut Stats),
+        init: impl FnOnce() -> Stats,
+    ) {
+        if let Some(i) = stations::index(city) {
+            match &mut self.stations[i] {
+                Some(stats) => update(stats),
+                slot @ None => *slot = Some(init()),
+            }
+            return;
+        }
+        let mut i = self.find(city);
+        match &mut self.slots[i] {
+            Some((_, stats)) => update(stats),
+            None => {
+                if 2 * (self.len + 1) > self.slots.len() {
+                    self.grow();
+                    i = self.find(city);
+                }
+                self.slots[i] = Some((city, init()));
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Index of the slot holding `city`, or of the empty slot it belongs in.
+    #[inline(always)]
+    fn find(&self, city: &[u8]) -> usize {
+        let mask = self.slots.len() - 1;
+        // Multiplicative hashes like Fx mix poorly into the low bits, so index
+        // with the high ones.
+        let shift = u64::BITS - self.slots.len().trailing_zeros();
+        let mut i = (self.hasher.hash_one(city) >> shift) as usize;
+        let mut step = 0;
+        loop {
+            match &self.slots[i] {
+                Some((key, _)) if *key != city => {
+                    step += 1;
+                    i = (i + step) & mask;
+                }
+                _ => return i,
+            }
+        }
+    }
+
+    #[cold]
+    fn grow(&mut self) {
+        let new_slots = (0..2 * self.slots.len()).map(|_| None).collect();
+        let old_slots = std::mem::replace(&mut self.slots, new_slots);
+        for (city, stats) in old_slots.into_iter().flatten() {
+            let i = self.find(city);
+            self.slots[i] = Some((city, stats));
+        }
+    }
+}
+
+impl<'a, S> CityTable<'a, S> {
+    pub fn into_entries(self) -> impl Iterator<Item = (&'a [u8], Stats)> {
+        let stations = self
+            .stations
+            .into_iter()
+            .zip(stations::STATIONS)
+            .filter_map(|(stats, city)| Some((city.as_bytes(), stats?)));
+        stations.chain(self.slots.into_iter().flatten())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        table::{CityTable, INITIAL_SLOTS},
+        Extras, Stats,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn count<'a>(table: &mut CityTable<'a>, city: &'a [u8]) {
+        table.update_or_insert_with(
+            city,
+            |stats| stats.count += 1,
+            || Stats::new(0, Extras::default()),
+        );
+    }
+
+    #[test]
+    fn it_finds_inserted_cities() {
+        let mut table = CityTable::default();
+        count(&mut table, b"Hamburg");
+        count(&mut table, b"Istanbul");
+        count(&mut table, b"Hamburg");
+
+        let mut entries: Vec<_> = table
+            .into_entries()
+            .map(|(city, stats)| (city, stats.count))
+            .collect();
+        entries.sort();
+        assert_eq!(vec![(&b"Hamburg"[..], 2), (&b"Istanbul"[..], 1)], entries);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn it_fits_a_slot_in_a_cache_line() {
+        assert_eq!(64, size_of::<Option<(&[u8], Stats)>>());
+        assert_eq!(
+            size_of::<(&[u8], Stats)>(),
+            size_of::<Option<(&[u8], Stats)>>()
+        );
+    }
+
+    #[test]
+    fn it_grows_past_the_initial_slots() {
+        let names: Vec<String> = (0..3 * INITIAL_SLOTS).map(|i| format!("C{i}")).collect();
+        let mut table = CityTable::default();
+        for _ in 0..2 {
+            for name in &names {
+                count(&mut table, name.as_bytes());
+            }
+        }
+        let counts: Vec<_> = table.into_entries().map(|(_, stats)| stats.count).collect();
+        assert_eq!(names.len(), counts.len());
+        assert!(counts.iter().all(|&count| count == 2));
+    }
+}
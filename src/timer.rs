@@ -0,0 +1,79 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// Records how long each named phase of a run took, in order.
+pub struct PhaseTimer {
+    start: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Default for PhaseTimer {
+    fn default() -> PhaseTimer {
+        PhaseTimer::new()
+    }
+}
+
+impl PhaseTimer {
+    pub fn new() -> PhaseTimer {
+        let now = Instant::now();
+        PhaseTimer {
+            start: now,
+            last: now,
+            phases: vec![],
+        }
+    }
+
+    /// Ends the phase `name`, which started when the previous one ended.
+    pub fn phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now - self.last));
+        self.last = now;
+    }
+
+    pub fn total(&self) -> Duration {
+        self.last - self.start
+    }
+
+    pub fn write_summary<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let total = self.total().as_secs_f64();
+        for (name, duration) in self.phases.iter().chain([&("total", self.total())]) {
+            let share = if total > 0.0 {
+                100.0 * duration.as_secs_f64() / total
+            } else {
+                0.0
+            };
+            writeln!(out, "{name:<12} {:>12.3?} {share:>5.1}%", duration)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::timer::PhaseTimer;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_summarises_phases_in_order() {
+        let mut timer = PhaseTimer::new();
+        timer.phase("open/mmap");
+        timer.phase("chunks");
+        timer.phase("output");
+
+        let mut out = vec![];
+        timer.write_summary(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let names: Vec<&str> = out
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(vec!["open/mmap", "chunks", "output", "total"], names);
+        assert_eq!(
+            timer.total(),
+            timer.phases.iter().map(|(_, duration)| *duration).sum()
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use io_uring::{opcode, types::Fd, IoUring};
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    os::fd::AsRawFd,
+};
+
+/// Bytes requested by each read submission.
+const BLOCK_SIZE: usize = 256 * 1024;
+/// Reads kept in flight at once.
+const QUEUE_DEPTH: u32 = 32;
+
+/// Reads the whole of `path` into memory with io_uring, keeping up to
+/// [`QUEUE_DEPTH`] block reads in flight instead of faulting in a mapping.
+///
+/// The kernel writes into the buffer until a read completes, so a failed
+/// read only stops new submissions: the error is returned once every read
+/// in flight has completed.
+pub fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    let mut buffer = vec![0u8; len];
+    let mut ring = IoUring::new(QUEUE_DEPTH)?;
+    let fd = Fd(file.as_raw_fd());
+
+    // Each read is identified by the file offset it starts at and always
+    // ends at the next block boundary, so short reads can be resumed.
+    let block_end = |offset: usize| ((offset / BLOCK_SIZE + 1) * BLOCK_SIZE).min(len);
+    let mut pending: Vec<usize> = (0..len).step_by(BLOCK_SIZE).rev().collect();
+    let mut in_flight = 0;
+    let mut error = None;
+    while in_flight > 0 || error.is_none() && !pending.is_empty() {
+        while error.is_none() && in_flight < QUEUE_DEPTH as usize {
+            let Some(offset) = pending.pop() else { break };
+            let read = opcode::Read::new(
+                fd,
+                buffer[offset..].as_mut_ptr(),
+                (block_end(offset) - offset) as u32,
+            )
+            .offset(offset as u64)
+            .build()
+            .user_data(offset as u64);
+            // SAFETY: `buffer` is neither dropped nor moved while a read is
+            // in flight, and every submitted range is disjoint from the
+            // others and inside `buffer`.
+            match unsafe { ring.submission().push(&read) } {
+                Ok(()) => in_flight += 1,
+                Err(_) => {
+                    error = Some(io::Error::other("io_uring submission queue is full"));
+                }
+            }
+        }
+        if in_flight == 0 {
+            break;
+        }
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => {
+                // Reads may still be in flight, and nothing tells when they
+                // will have completed, so the buffer must never be freed.
+                std::mem::forget(buffer);
+                return Err(err);
+            }
+        }
+        for completion in ring.completion() {
+            in_flight -= 1;
+            let offset = completion.user_data() as usize;
+            match completion.result() {
+                err if err < 0 => {
+                    error.get_or_insert(io::Error::from_raw_os_error(-err));
+                }
+                0 => {
+                    error.get_or_insert(ErrorKind::UnexpectedEof.into());
+                }
+                read if offset + (read as usize) < block_end(offset) => {
+                    pending.push(offset + read as usize)
+                }
+                _ => {}
+            }
+        }
+    }
+    match error {
+        Some(err) => Err(err),
+        None => Ok(buffer),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::uring::{read_file, BLOCK_SIZE, QUEUE_DEPTH};
+    use pretty_assertions::assert_eq;
+    use std::{env, fs, io::ErrorKind};
+
+    #[test]
+    fn it_reads_more_blocks_than_fit_in_flight() {
+        let len = (QUEUE_DEPTH as usize + 3) * BLOCK_SIZE + 123;
+        let content: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let path = env::temp_dir().join(format!("onebrc-uring-{}", std::process::id()));
+        fs::write(&path, &content).unwrap();
+        let read = read_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(read.unwrap() == content);
+    }
+
+    #[test]
+    fn it_returns_the_error_of_a_failed_read() {
+        let dir = env::temp_dir();
+        let err = read_file(dir.to_str().unwrap()).unwrap_err();
+        assert_eq!(ErrorKind::IsADirectory, err.kind());
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// Largest difference between two averages that still counts as a match,
+/// enough to absorb rounding to a different number of decimals.
+const AVG_TOLERANCE: f64 = 0.005;
+
+/// The `min/avg/max` printed for one city.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}/{:.2}/{:.1}", self.min, self.avg, self.max)
+    }
+}
+
+/// A city whose stats differ, or that only one side has.
+#[derive(Debug, PartialEq)]
+pub struct Discrepancy {
+    pub city: String,
+    pub expected: Option<Summary>,
+    pub actual: Option<Summary>,
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: expected ", self.city)?;
+        match &self.expected {
+            Some(summary) => write!(f, "{summary}")?,
+            None => write!(f, "nothing")?,
+        }
+        write!(f, ", got ")?;
+        match &self.actual {
+            Some(summary) => write!(f, "{summary}"),
+            None => write!(f, "nothing"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MalformedEntry(pub String);
+
+impl Display for MalformedEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed entry '{}'", self.0)
+    }
+}
+
+/// Parses results in the default `{city=min/avg/max, ...}` format. Columns
+/// after `max` are ignored, and city names may not contain `", "`.
+pub fn parse_results(results: &str) -> Result<BTreeMap<String, Summary>, MalformedEntry> {
+    let results = results.trim();
+    let entries = results
+        .strip_prefix('{')
+        .and_then(|results| results.strip_suffix('}'))
+        .ok_or_else(|| MalformedEntry(results.to_string()))?;
+    if entries.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    entries
+        .split(", ")
+        .map(|entry| {
+            let malformed = || MalformedEntry(entry.to_string());
+            let (city, stats) = entry.rsplit_once('=').ok_or_else(malformed)?;
+            let mut stats = stats.split('/').map(str::parse::<f64>);
+            let mut next = || stats.next().and_then(Result::ok).ok_or_else(malformed);
+            let summary = Summary {
+                min: next()?,
+                avg: next()?,
+                max: next()?,
+            };
+            Ok((city.to_string(), summary))
+        })
+        .collect()
+}
+
+/// Lists every city, in name order, whose min or max differ from `expected`
+/// or whose average is off by more than [`AVG_TOLERANCE`].
+pub fn compare(
+    expected: &BTreeMap<String, Summary>,
+    actual: &BTreeMap<String, Summary>,
+) -> Vec<Discrepancy> {
+    let mut cities: Vec<&String> = expected.keys().chain(actual.keys()).collect();
+    cities.sort_unstable();
+    cities.dedup();
+    cities
+        .into_iter()
+        .filter_map(|city| {
+            let expected = expected.get(city).copied();
+            let actual = actual.get(city).copied();
+            let matches = match (&expected, &actual) {
+                (Some(expected), Some(actual)) => {
+                    expected.min == actual.min
+                        && expected.max == actual.max
+                        && (expected.avg - actual.avg).abs() <= AVG_TOLERANCE
+                }
+                _ => false,
+            };
+            (!matches).then(|| Discrepancy {
+                city: city.clone(),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::validate::{compare, parse_results, Discrepancy, MalformedEntry, Summary};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_parses_results() {
+        let results =
+            parse_results("{Istanbul=6.2/14.60/23.0/2, St. John's=15.2/15.2/15.2}\n").unwrap();
+        assert_eq!(
+            vec![
+                (
+                    "Istanbul".to_string(),
+                    Summary {
+                        min: 6.2,
+                        avg: 14.6,
+                        max: 23.0
+                    }
+                ),
+                (
+                    "St. John's".to_string(),
+                    Summary {
+                        min: 15.2,
+                        avg: 15.2,
+                        max: 15.2
+                    }
+                ),
+            ],
+            results.into_iter().collect::<Vec<_>>()
+        );
+        assert!(parse_results("{}").unwrap().is_empty());
+        assert_eq!(
+            Err(MalformedEntry("Hamburg=1.0/2.0".to_string())),
+            parse_results("{Hamburg=1.0/2.0}")
+        );
+        assert!(parse_results("Hamburg=1.0/1.0/1.0").is_err());
+    }
+
+    #[test]
+    fn it_compares_within_the_average_tolerance() {
+        let expected = parse_results("{A=1.0/2.00/3.0, B=1.0/2.00/3.0, C=1.0/1.0/1.0}").unwrap();
+        let actual = parse_results("{A=1.0/2.004/3.0, B=1.0/2.01/3.0, D=1.0/1.0/1.0}").unwrap();
+        let summary = |avg| Summary {
+            min: 1.0,
+            avg,
+            max: 3.0,
+        };
+        let single = Summary {
+            min: 1.0,
+            avg: 1.0,
+            max: 1.0,
+        };
+        assert_eq!(
+            vec![
+                Discrepancy {
+                    city: "B".to_string(),
+                    expected: Some(summary(2.0)),
+                    actual: Some(summary(2.01)),
+                },
+                Discrepancy {
+                    city: "C".to_string(),
+                    expected: Some(single),
+                    actual: None,
+                },
+                Discrepancy {
+                    city: "D".to_string(),
+                    expected: None,
+                    actual: Some(single),
+                },
+            ],
+            compare(&expected, &actual)
+        );
+        assert_eq!(
+            "C: expected 1.0/1.00/1.0, got nothing",
+            compare(&expected, &actual)[1].to_string()
+        );
+    }
+
+    #[test]
+    fn it_flags_different_extremes() {
+        let expected = parse_results("{A=1.0/2.00/3.0}").unwrap();
+        let actual = parse_results("{A=1.1/2.00/3.0}").unwrap();
+        assert_eq!(1, compare(&expected, &actual).len());
+    }
+}
@@ -0,0 +1,607 @@
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn onebrc(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+#[test]
+fn it_keeps_timing_out_of_stdout() {
+    let output = onebrc(&[]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("{Bridgetown=26.9/26.90/26.9, "));
+    assert!(stdout.ends_with("St. John's=15.2/15.20/15.2}\n"));
+    assert!(!stdout.contains("µs") && !stdout.contains("ms"));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.trim_end().ends_with('s'), "{stderr}");
+}
+
+#[test]
+fn it_suppresses_timing() {
+    let output = onebrc(&["--no-timing"]);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn it_skips_output_on_dry_run() {
+    let output = onebrc(&["--dry-run"]);
+    assert!(output.stdout.is_empty());
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn it_reports_the_exact_row_count() {
+    let output = onebrc(&["--verbose", "--filter", "Istanbul"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("\nrows         10\n"), "{stderr}");
+    assert!(stderr.contains(" rows/s"), "{stderr}");
+}
+
+#[test]
+fn it_reads_without_mmap() {
+    let mapped = onebrc(&["--no-timing"]);
+    let read = onebrc(&["--no-timing", "--no-mmap"]);
+    assert_eq!(mapped.stdout, read.stdout);
+}
+
+#[test]
+fn it_generates_a_reproducible_file() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let first = format!("{dir}/generated-1.txt");
+    let second = format!("{dir}/generated-2.txt");
+    for path in [&first, &second] {
+        let args = ["generate", "--rows", "1000", "--cities", "7", "--seed", "3"];
+        onebrc(&[&args[..], &["--output", path]].concat());
+    }
+    let generated = fs::read(&first).unwrap();
+    assert_eq!(generated, fs::read(&second).unwrap());
+    assert_eq!(1000, generated.iter().filter(|&&b| b == b'\n').count());
+
+    let output = onebrc(&["--no-timing", "--count", &first]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(7, stdout.matches('=').count(), "{stdout}");
+}
+
+#[test]
+fn it_validates_against_a_reference() {
+    let expected = format!("{}/expected.txt", env!("CARGO_TARGET_TMPDIR"));
+    onebrc(&["--no-timing", "--output", &expected]);
+    let output = onebrc(&[
+        "validate",
+        "--input",
+        "measurements.txt",
+        "--expected",
+        &expected,
+    ]);
+    assert!(output.stdout.is_empty());
+
+    let results = fs::read_to_string(&expected).unwrap();
+    fs::write(&expected, results.replace("Hamburg=12.0", "Hamburg=11.9")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args([
+            "validate",
+            "--input",
+            "measurements.txt",
+            "--expected",
+            &expected,
+        ])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "Hamburg: expected 11.9/12.00/12.0, got 12.0/12.00/12.0\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+/// Results of a single worker over one chunk, and of several workers over
+/// chunks of each of `chunk_sizes`, which should all be the same.
+fn assert_thread_counts_agree(path: &str, chunk_sizes: &[&str]) {
+    let single = onebrc(&["--no-timing", "--count", "--stddev", "--threads", "1", path]);
+    for chunk_size in chunk_sizes {
+        let args = ["--no-timing", "--count", "--stddev", "--threads", "4"];
+        let multi = onebrc(&[&args[..], &["--chunk-size", chunk_size, path]].concat());
+        assert_eq!(
+            String::from_utf8_lossy(&single.stdout),
+            String::from_utf8_lossy(&multi.stdout),
+            "--chunk-size {chunk_size}"
+        );
+    }
+}
+
+#[test]
+fn it_matches_a_single_thread_on_the_test_corpus() {
+    // 137 bytes, so 128 splits the ninth row.
+    assert_thread_counts_agree("measurements.txt", &["128", "40000"]);
+}
+
+#[test]
+fn it_matches_a_single_thread_across_chunk_boundaries() {
+    let path = format!("{}/boundaries.txt", env!("CARGO_TARGET_TMPDIR"));
+    let mut content = String::new();
+    for i in 0..500 {
+        let city = ["Hamburg", "Bulawayo", "Palembang", "St. John's"][i % 4];
+        content += &format!("{city};{}.{}\n", (i % 90) as i32 - 45, i % 10);
+        // Only ever in the one chunk that holds this row.
+        if i == 250 {
+            content += "Cracow;-12.6\n";
+        }
+    }
+    fs::write(&path, content).unwrap();
+    // Row lengths vary, so these sizes mostly end in the middle of a row.
+    assert_thread_counts_agree(&path, &["128", "131", "1000", "4097"]);
+}
+
+#[test]
+fn it_counts_lines() {
+    let output = onebrc(&["--line-count"]);
+    assert_eq!("10\n", String::from_utf8(output.stdout).unwrap());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn it_counts_unique_cities() {
+    // Istanbul appears twice.
+    let output = onebrc(&["--unique-cities"]);
+    assert_eq!("9\n", String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn it_skips_a_byte_order_mark() {
+    let path = format!("{}/bom.txt", env!("CARGO_TARGET_TMPDIR"));
+    let content = fs::read("measurements.txt").unwrap();
+    fs::write(&path, [b"\xEF\xBB\xBF".as_slice(), &content].concat()).unwrap();
+    let plain = onebrc(&["--no-timing", "--threads", "2", "--chunk-size", "128"]);
+    let bom = onebrc(&[
+        "--no-timing",
+        "--threads",
+        "2",
+        "--chunk-size",
+        "128",
+        &path,
+    ]);
+    assert_eq!(plain.stdout, bom.stdout);
+}
+
+#[test]
+fn it_reports_a_missing_input_without_panicking() {
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .arg("does-not-exist.txt")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Cannot open 'does-not-exist.txt': "),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn it_rejects_city_names_that_are_not_utf8() {
+    let path = format!("{}/invalid-utf8.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, b"Hamburg;12.0\nK\xf6ln;8.5\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        format!("Malformed input: city name at byte 13 of '{path}' is not UTF-8\n"),
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn it_reports_the_line_of_a_malformed_row() {
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let path = format!("{}/malformed.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(
+        &path,
+        format!("city;temperature\n{corpus}{corpus}Hamburg 12.0\n"),
+    )
+    .unwrap();
+    for args in [
+        &["--skip-header", "--threads", "1"][..],
+        &["--skip-header", "--threads", "4", "--chunk-size", "128"],
+        &["--skip-header", "--unique-cities"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+            .args(args)
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!(
+            format!("Malformed input: line 22 of '{path}': row has no ';' separator\n"),
+            String::from_utf8(output.stderr).unwrap(),
+            "{args:?}"
+        );
+    }
+}
+
+#[test]
+fn it_checks_the_input_without_aggregating() {
+    let output = onebrc(&["--check"]);
+    assert_eq!(
+        "10 lines checked, 0 errors\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let path = format!("{}/check.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(
+        &path,
+        b"Hamburg;12.0\nHamburg 12.0\nK\xf6ln;8.5\nOslo;1.0\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(["--check", &path])
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "4 lines checked, 2 errors\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert_eq!(
+        format!(
+            "line 2 of '{path}': row has no ';' separator\n\
+             line 3 of '{path}': city name is not UTF-8\n\
+             2 lines are malformed\n"
+        ),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn it_prints_fahrenheit() {
+    let output = onebrc(&["--no-timing", "--fahrenheit"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("{Bridgetown=80.4°F/80.42°F/80.4°F, "));
+    assert!(stdout.contains("Istanbul=43.2°F/58.28°F/73.4°F"));
+}
+
+#[test]
+fn it_prints_one_city_per_line_for_sort() {
+    let by_name = onebrc(&["--no-timing", "--one-per-line"]);
+    let stdout = String::from_utf8(by_name.stdout).unwrap();
+    assert!(stdout.starts_with("Bridgetown=26.9/26.90/26.9\nBulawayo=8.9/8.90/8.9\n"));
+    assert_eq!(9, stdout.lines().count());
+
+    let mut by_max = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(["--no-timing", "--one-per-line", "--sort-by", "max"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let sorted = Command::new("sort")
+        .env("LC_ALL", "C")
+        .stdin(by_max.stdout.take().unwrap())
+        .output()
+        .unwrap();
+    assert!(by_max.wait().unwrap().success());
+    assert!(sorted.status.success(), "{sorted:?}");
+    assert_eq!(stdout, String::from_utf8(sorted.stdout).unwrap());
+}
+
+#[test]
+fn it_prints_histograms_after_the_results() {
+    let output = onebrc(&["--no-timing", "--ascii-histogram", "--filter", "Istanbul"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!("{Istanbul=6.2/14.60/23.0}", lines[0]);
+    assert_eq!("Istanbul", lines[1]);
+    assert_eq!(3, lines[2..].len(), "{stdout}");
+    assert!(lines[2].starts_with("     0..10   █") && lines[2].ends_with("█ 1"));
+    assert_eq!("    10..20    0", lines[3]);
+    // Not a terminal, so the bars fill 80 characters.
+    assert_eq!(80, lines[4].chars().count());
+}
+
+#[test]
+fn it_prints_the_selected_statistics() {
+    let output = onebrc(&["--no-timing", "--select", "max,min", "--count"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("{Bridgetown=26.9/26.9/1, "), "{stdout}");
+    assert!(stdout.contains("Istanbul=23.0/6.2/2"), "{stdout}");
+
+    let output = onebrc(&["--no-timing", "--select", "count,min", "--count"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("{Bridgetown=1/26.9, "), "{stdout}");
+    let args = [
+        "--no-timing",
+        "--format",
+        "csv",
+        "--select",
+        "count,min",
+        "--count",
+    ];
+    let output = onebrc(&args);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("city,count,min\n"), "{stdout}");
+}
+
+#[test]
+fn it_appends_the_percentiles() {
+    let output = onebrc(&[
+        "--no-timing",
+        "--percentiles",
+        "p0,p50,p100",
+        "--filter",
+        "istanbul",
+    ]);
+    assert_eq!(
+        "{Istanbul=6.2/14.60/23.0/6.2/14.6/23.0}\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn it_prints_a_summary_of_the_whole_dataset() {
+    let output = onebrc(&["--no-timing", "--summary", "--filter", "hamburg"]);
+    assert_eq!(
+        "{Hamburg=12.0/12.00/12.0}\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert_eq!(
+        "rows         10\n\
+         cities       9\n\
+         min          6.2\n\
+         max          38.8\n\
+         mean         20.92\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn it_drops_cities_with_few_measurements() {
+    let output = onebrc(&["--no-timing", "--count", "--min-count", "2"]);
+    assert_eq!(
+        "{Istanbul=6.2/14.60/23.0/2}\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn it_reads_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(["--no-timing", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let corpus = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    child.stdin.take().unwrap().write_all(&corpus).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(onebrc(&["--no-timing"]).stdout, output.stdout);
+}
+
+#[test]
+fn it_merges_several_files() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let split = corpus.match_indices('\n').nth(4).unwrap().0 + 1;
+    let first = format!("{dir}/first-half.txt");
+    let second = format!("{dir}/second-half.txt");
+    fs::write(&first, &corpus[..split]).unwrap();
+    fs::write(&second, &corpus[split..]).unwrap();
+
+    let args = ["--no-timing", "--count", "--stddev", "--median"];
+    let whole = onebrc(&args);
+    let merged = onebrc(&[&args[..], &[&first, &second]].concat());
+    assert_eq!(
+        String::from_utf8_lossy(&whole.stdout),
+        String::from_utf8_lossy(&merged.stdout)
+    );
+    let line_count = onebrc(&["--line-count", &first, &second]);
+    assert_eq!("10\n", String::from_utf8_lossy(&line_count.stdout));
+}
+
+#[test]
+fn it_merges_raw_results_of_separate_runs() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let split = corpus.match_indices('\n').nth(4).unwrap().0 + 1;
+    let first = format!("{dir}/first-run.txt");
+    let second = format!("{dir}/second-run.txt");
+    fs::write(&first, &corpus[..split]).unwrap();
+    fs::write(&second, &corpus[split..]).unwrap();
+    let first_raw = format!("{dir}/first-run.raw");
+    let second_raw = format!("{dir}/second-run.raw");
+    onebrc(&[
+        "--no-timing",
+        "--format",
+        "raw",
+        "--output",
+        &first_raw,
+        &first,
+    ]);
+    onebrc(&[
+        "--no-timing",
+        "--format",
+        "raw",
+        "--output",
+        &second_raw,
+        &second,
+    ]);
+    let raw = fs::read_to_string(&first_raw).unwrap();
+    assert!(raw.contains("Hamburg\t120\t1\t120\t120\n"), "{raw}");
+
+    let whole = onebrc(&["--no-timing"]);
+    let merged = onebrc(&["merge", "--raw", &first_raw, &second_raw]);
+    assert_eq!(
+        String::from_utf8_lossy(&whole.stdout),
+        String::from_utf8_lossy(&merged.stdout)
+    );
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn it_reads_gzip_files() {
+    use flate2::{write::GzEncoder, Compression};
+
+    let corpus = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&corpus).unwrap();
+    let path = format!("{}/measurements.txt.gz", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+    let args = ["--no-timing", "--count", "--stddev"];
+    let plain = onebrc(&args);
+    let gzip = onebrc(&[&args[..], &["--gzip", &path]].concat());
+    assert_eq!(
+        String::from_utf8_lossy(&plain.stdout),
+        String::from_utf8_lossy(&gzip.stdout)
+    );
+
+    let verbose = onebrc(&["--verbose", "--gzip", &path]);
+    let stderr = String::from_utf8(verbose.stderr).unwrap();
+    assert!(stderr.contains("\ndecompressed 0.00 MB\n"), "{stderr}");
+    assert!(!stderr.contains("file size"), "{stderr}");
+}
+
+#[test]
+fn it_prints_no_cities_for_an_empty_file() {
+    let path = format!("{}/empty.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, "").unwrap();
+    for args in [&["--threads", "1"][..], &["--threads", "4"], &["--no-mmap"]] {
+        let output = onebrc(&[&["--no-timing"], args, &[&path]].concat());
+        assert_eq!("{}\n", String::from_utf8_lossy(&output.stdout), "{args:?}");
+    }
+}
+
+#[test]
+fn it_reads_a_last_row_without_newline() {
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let path = format!("{}/unterminated.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, corpus.strip_suffix('\n').unwrap()).unwrap();
+    for threads in ["1", "4"] {
+        let args = ["--no-timing", "--count", "--threads", threads];
+        assert_eq!(
+            String::from_utf8_lossy(&onebrc(&args).stdout),
+            String::from_utf8_lossy(&onebrc(&[&args[..], &[&path]].concat()).stdout)
+        );
+    }
+}
+
+#[test]
+fn it_skips_a_header() {
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let path = format!("{}/header.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, format!("city;temperature\n{corpus}")).unwrap();
+    for threads in ["1", "4"] {
+        let args = ["--no-timing", "--count", "--threads", threads];
+        let skipped = onebrc(&[&args[..], &["--skip-header", &path]].concat());
+        assert_eq!(
+            String::from_utf8_lossy(&onebrc(&args).stdout),
+            String::from_utf8_lossy(&skipped.stdout)
+        );
+    }
+    let line_count = onebrc(&["--line-count", "--skip-header", &path]);
+    assert_eq!("10\n", String::from_utf8_lossy(&line_count.stdout));
+}
+
+#[test]
+fn it_skips_comments() {
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let commented: String = corpus
+        .lines()
+        .enumerate()
+        .map(|(i, row)| format!("# row {i}\n{row}\n#Hamburg;99.9\n"))
+        .collect();
+    let path = format!("{}/comments.txt", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, commented).unwrap();
+    for threads in ["1", "4"] {
+        let args = ["--no-timing", "--count", "--threads", threads];
+        let skipped = onebrc(&[&args[..], &["--allow-comments", &path]].concat());
+        assert_eq!(
+            String::from_utf8_lossy(&onebrc(&args).stdout),
+            String::from_utf8_lossy(&skipped.stdout)
+        );
+    }
+    let line_count = onebrc(&["--line-count", "--allow-comments", &path]);
+    assert_eq!("10\n", String::from_utf8_lossy(&line_count.stdout));
+    let unique_cities = onebrc(&["--unique-cities", "--allow-comments", &path]);
+    assert_eq!("9\n", String::from_utf8_lossy(&unique_cities.stdout));
+}
+
+#[test]
+fn it_reads_other_delimiters() {
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let args = ["--no-timing", "--count"];
+    let expected = onebrc(&args).stdout;
+    for (name, delimiter, flag) in [("tab", "\t", "\\t"), ("pipe", "|", "|")] {
+        let path = format!("{}/{name}.txt", env!("CARGO_TARGET_TMPDIR"));
+        fs::write(&path, corpus.replace(';', delimiter)).unwrap();
+        let output = onebrc(&[&args[..], &["--delimiter", flag, &path]].concat());
+        assert_eq!(
+            String::from_utf8_lossy(&expected),
+            String::from_utf8_lossy(&output.stdout),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn it_warns_about_out_of_range_temperatures() {
+    let corpus =
+        fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/measurements.txt")).unwrap();
+    let [valid, out_of_range] =
+        ["valid", "out_of_range"].map(|name| format!("{}/{name}.txt", env!("CARGO_TARGET_TMPDIR")));
+    fs::write(&valid, corpus.repeat(3)).unwrap();
+    // Ten rows per copy, so the second warning comes from a later chunk.
+    let rows = format!("city;temperature\n{corpus}Hamburg;100.0\n{corpus}Oslo;-120.5\n{corpus}");
+    fs::write(&out_of_range, rows).unwrap();
+    let expected = onebrc(&["--no-timing", "--count", &valid]).stdout;
+    for (threads, chunk_size) in [("1", "1048576"), ("4", "128")] {
+        let output = onebrc(&[
+            "--no-timing",
+            "--count",
+            "--skip-header",
+            "--validate-range",
+            "--threads",
+            threads,
+            "--chunk-size",
+            chunk_size,
+            &out_of_range,
+        ]);
+        assert_eq!(
+            String::from_utf8_lossy(&expected),
+            String::from_utf8_lossy(&output.stdout)
+        );
+        let mut warnings: Vec<_> = String::from_utf8(output.stderr)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        warnings.sort();
+        assert_eq!(
+            vec![
+                "Warning: line 12: city='Hamburg' temperature=100.0 out of range",
+                "Warning: line 23: city='Oslo' temperature=-120.5 out of range",
+            ],
+            warnings
+        );
+    }
+}